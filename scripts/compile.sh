@@ -17,9 +17,7 @@ compile_scss() {
     # Main.scss contains bootsrap and theme-independent layout
     sass --style=compressed --update --no-source-map --load-path scss/ --load-path node_modules/ scss/main.scss css/main.css
 
-    # locals
-    sass --style=compressed --update --no-source-map --load-path scss/ scss/locals/de.scss css/de.css
-    sass --style=compressed --update --no-source-map --load-path scss/ scss/locals/de.scss css/en.css
+    # localized static texts are generated by mdpdf at render time (see src/locales/)
 
     # themes
     sass --style=compressed --update --no-source-map --load-path scss/ scss/themes/night.scss css/night.css
@@ -45,18 +45,62 @@ pub fn app() -> App<'static, 'static> {
         )
         .arg(
             Arg::with_name("extensions")
-            .help("Commonmark Extensions to be used. By default all are activated. Commas are supported as separators when specifying multiple.")
+            .help("Commonmark and mdpdf extensions to be used. By default all are activated. Commas are supported as separators when specifying multiple.")
             .takes_value(true)
-            .possible_values(&["footnotes", "table", "tasklist", "smart-punctuation", "strikethrough"])
+            .possible_values(&["footnotes", "table", "tasklist", "smart-punctuation", "strikethrough", "definition-lists", "abbreviations", "superscript", "subscript", "highlight", "keyboard", "emoji"])
+            .use_delimiter(true)
             .long("--extensions")
         )
         .arg(
             Arg::with_name("pagesize")
             .long("--pagesize")
             .takes_value(true)
-            .help("PDF pagesize")
+            .help("PDF pagesize (A0-A9, B0-B10, Letter, Legal, Executive, Tabloid, Ledger, Folio, ...)")
             .default_value("A4")
-            .possible_values(&["A3", "A4", "A5", "A6"])
+        )
+        .arg(
+            Arg::with_name("page-width")
+            .long("--page-width")
+            .takes_value(true)
+            .requires("page-height")
+            .help("Custom page width with unit mm, cm or in (e.g. 210mm). Overrides --pagesize")
+        )
+        .arg(
+            Arg::with_name("page-height")
+            .long("--page-height")
+            .takes_value(true)
+            .requires("page-width")
+            .help("Custom page height with unit mm, cm or in (e.g. 11in). Overrides --pagesize")
+        )
+        .arg(
+            Arg::with_name("margin")
+            .long("--margin")
+            .takes_value(true)
+            .help("Page margin for all sides with unit mm, cm or in [Default: 10mm]")
+        )
+        .arg(
+            Arg::with_name("margin-top")
+            .long("--margin-top")
+            .takes_value(true)
+            .help("Top page margin (overrides --margin)")
+        )
+        .arg(
+            Arg::with_name("margin-right")
+            .long("--margin-right")
+            .takes_value(true)
+            .help("Right page margin (overrides --margin)")
+        )
+        .arg(
+            Arg::with_name("margin-bottom")
+            .long("--margin-bottom")
+            .takes_value(true)
+            .help("Bottom page margin (overrides --margin)")
+        )
+        .arg(
+            Arg::with_name("margin-left")
+            .long("--margin-left")
+            .takes_value(true)
+            .help("Left page margin (overrides --margin)")
         )
         .arg(
             Arg::with_name("date")
@@ -70,7 +114,85 @@ pub fn app() -> App<'static, 'static> {
             Arg::with_name("keep")
             .short("-k")
             .takes_value(false)
-            .help("Keep temporary files (including body and footer")
+            .help("Keep temporary files (body, header and footer) and print their directory")
+        )
+        .arg(
+            Arg::with_name("max-image-size")
+            .long("--max-image-size")
+            .takes_value(true)
+            .help("Downscale embedded images whose width or height exceeds this number of pixels")
+        )
+        .arg(
+            Arg::with_name("image-quality")
+            .long("--image-quality")
+            .takes_value(true)
+            .help("Recompress embedded images without transparency as JPEG with this quality (1-100)")
+        )
+        .arg(
+            Arg::with_name("footnotes")
+            .long("--footnotes")
+            .takes_value(true)
+            .possible_values(&["end", "block"])
+            .help("Placement of footnotes: end collects them in a section at the end of the document, block puts them right after the block (e.g. paragraph or list) referencing them [Default: end]")
+        )
+        .arg(
+            Arg::with_name("number-sections")
+            .long("--number-sections")
+            .help("Number headings (e.g. 2.3 Results), headings with {-} stay unnumbered. Can be set with 'number-sections: true' in the front matter too")
+        )
+        .arg(
+            Arg::with_name("bibliography")
+            .long("--bibliography")
+            .takes_value(true)
+            .help("Bibliography in BibTeX (.bib) or CSL-JSON (.json) format for [@key] citations. Can be set with 'bibliography' in the front matter too")
+        )
+        .arg(
+            Arg::with_name("citation-style")
+            .long("--citation-style")
+            .takes_value(true)
+            .possible_values(&["numeric", "author-year"])
+            .help("Style of citations: numeric ([1]) or author-year ((Doe 2020)). Can be set with 'citation-style' in the front matter too [Default: numeric]")
+        )
+        .arg(
+            Arg::with_name("zebra")
+            .long("--zebra")
+            .help("Alternate the background of table rows (zebra striping)")
+        )
+        .arg(
+            Arg::with_name("landscape-tables")
+            .long("--landscape-tables")
+            .takes_value(true)
+            .help("Rotate tables with more than this number of columns to landscape on their own page")
+        )
+        .arg(
+            Arg::with_name("columns")
+            .long("--columns")
+            .takes_value(true)
+            .help("Number of columns for the document body (1-6). Can be set with 'columns' in the front matter too [Default: 1]")
+        )
+        .arg(
+            Arg::with_name("column-span")
+            .long("--column-span")
+            .takes_value(true)
+            .help("Headings up to this level span all columns (1-6). Can be set with 'column-span' in the front matter too [Default: 1]")
+        )
+        .arg(
+            Arg::with_name("print")
+            .long("--print")
+            .help("Print mode for duplex printing and binding: mirrored gutter on even pages and chapters (h1) starting on odd pages")
+        )
+        .arg(
+            Arg::with_name("gutter")
+            .long("--gutter")
+            .takes_value(true)
+            .requires("print")
+            .help("Additional inner margin for binding in print mode with unit mm, cm or in [Default: 15mm]")
+        )
+        .arg(
+            Arg::with_name("crop-marks")
+            .long("--crop-marks")
+            .requires("print")
+            .help("Add crop marks around the pages in print mode")
         )
         .arg(
             Arg::with_name("orientation")
@@ -85,32 +207,153 @@ pub fn app() -> App<'static, 'static> {
             .short("-s")
             .long("--stylesheet")
             .takes_value(true)
-            .help("Custom css stylesheet in addition to theme")
+            .multiple(true)
+            .number_of_values(1)
+            .help("Custom css stylesheet in addition to theme, a local file or an http(s) url (see --remote). May be repeated, later ones are loaded last")
+        )
+        .arg(
+            Arg::with_name("var")
+            .long("--var")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Override a theme variable (e.g. --var primary=#c00 --var font-size=11pt)")
+            .long_help("Override a theme variable. Possible variables: primary, primary-variant, text, highlight, special, special-bg, special-text, special-highlight, zebra, font-size")
         )
         .arg(
             Arg::with_name("theme")
             .long("--theme")
-            .help("Theme for document")
+            .help("Theme for document: lime, light, night, a user theme or a path to a theme package")
+            .long_help("Theme for document. Either a built-in theme (lime, light, night), the name of a theme package in ~/.config/mdpdf/themes/ or a path to a theme package directory. Use 'mdpdf themes list' to see all available themes")
             .takes_value(true)
             .default_value("light")
-            .possible_values(&["lime", "light", "night"])
         )
         .arg(
-            Arg::with_name("de")
-            .short("-d")
-            .long("--german")
-            .help("Static content in german") // ATM only affecting footer
+            Arg::with_name("theme-vars")
+            .long("--theme-vars")
+            .takes_value(true)
+            .help("SCSS file with theme variables ($primary, $special-bg, ...) compiled on top of the built-in theme")
+            .long_help("SCSS file with theme variables compiled on top of the selected built-in theme. Possible variables: $primary, $primary-variant, $text, $highlight, $special, $special-bg, $special-text, $special-highlight, $zebra (see src/assets/scss/themes/*-variables.scss)")
+        )
+        .arg(
+            Arg::with_name("bundled-fonts")
+            .long("--bundled-fonts")
+            .help("Use the bundled fonts (DejaVu Sans, DejaVu Sans Mono) for everything not set with --font-body, --font-heading or --font-mono so the output doesn't depend on the fonts of the host")
+        )
+        .arg(
+            Arg::with_name("font-body")
+            .long("--font-body")
+            .takes_value(true)
+            .help("Font for body text: a font file (ttf, otf, woff, woff2), a bundled font (DejaVu Sans, DejaVu Sans Mono), an installed font family or 'system' for the default font of the host [Default: system, DejaVu Sans with --bundled-fonts]")
+        )
+        .arg(
+            Arg::with_name("font-heading")
+            .long("--font-heading")
+            .takes_value(true)
+            .help("Font for headings: a font file, a bundled font, an installed font family or 'system' [Default: system, DejaVu Sans with --bundled-fonts]")
         )
         .arg(
-            Arg::with_name("safe")
+            Arg::with_name("font-mono")
+            .long("--font-mono")
+            .takes_value(true)
+            .help("Font for code: a font file, a bundled font, an installed font family or 'system' [Default: system, DejaVu Sans Mono with --bundled-fonts]")
+        )
+        .arg(
+            Arg::with_name("lang")
+            .long("--lang")
+            .takes_value(true)
+            .default_value("en")
+            .help("Language for static content as BCP-47 tag (e.g. de or de-AT). Built-in: en, de, fr, es, nl")
+        )
+        .arg(
+            Arg::with_name("translations")
+            .long("--translations")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Translation catalog file with 'key = value' lines overriding the built-in texts")
+        )
+        .arg(
+            Arg::with_name("unsafe")
             .long("--unsafe")
-            .help("Don't clean html before converting with wkhtmltopdf") // ATM only affecting footer
+            .help("Don't clean the html before converting with wkhtmltopdf (same as --sanitize none)")
+            .conflicts_with_all(&["sanitize", "sanitize-policy"])
+        )
+        .arg(
+            Arg::with_name("sanitize")
+            .long("--sanitize")
+            .takes_value(true)
+            .possible_values(&["strict", "default", "permissive", "none"])
+            .help("Sanitization policy for raw html in the document [Default: default]")
+            .long_help("Sanitization policy for raw html in the document. strict only allows the markup generated from markdown, default additionally allows common html tags and inline styles, permissive allows all classes, ids and some layout tags (e.g. svg) and none disables sanitization [Default: default]")
+        )
+        .arg(
+            Arg::with_name("sanitize-policy")
+            .long("--sanitize-policy")
+            .takes_value(true)
+            .help("Policy file with additionally allowed tags, attributes, classes and url schemes ('tags = details, summary')")
+        )
+        .arg(
+            Arg::with_name("allow-js")
+            .long("--allow-js")
+            .help("Enable JavaScript while rendering (disabled by default)")
+        )
+        .arg(
+            Arg::with_name("allow-path")
+            .long("--allow-path")
+            .takes_value(true)
+            .multiple(true)
+            .number_of_values(1)
+            .help("Allow embedding local files (e.g. images) from this directory tree. By default only the directory of INPUT is allowed (may be used multiple times)")
+        )
+        .arg(
+            Arg::with_name("remote")
+            .long("--remote")
+            .takes_value(true)
+            .possible_values(&["deny", "allow", "cache"])
+            .help("Policy for remote images and stylesheets: deny them, fetch them on each run or fetch them once into a local cache. Fetched resources are embedded [Default: allow]")
+        )
+        .arg(
+            Arg::with_name("remote-cache")
+            .long("--remote-cache")
+            .takes_value(true)
+            .help("Cache directory for --remote=cache [Default: ~/.cache/mdpdf/remote]")
+        )
+        .arg(
+            Arg::with_name("verbose")
+            .short("-v")
+            .long("--verbose")
+            .help("Print additional information, e.g. what was removed by the sanitizer")
         )
         .arg(
             Arg::with_name("toc")
             .help("Add table of contents (not implemented ATM)")
             .long("--toc")
         )
+        .arg(
+            Arg::with_name("check-links")
+            .long("--check-links")
+            .takes_value(true)
+            .possible_values(&["off", "warn", "error"])
+            .help("Check internal links (#anchor) and relative links to files: warn about broken links or fail on them [Default: warn]")
+        )
+        .arg(
+            Arg::with_name("lof")
+            .long("--lof")
+            .help("Add a list of figures with page numbers at the beginning of the document")
+        )
+        .arg(
+            Arg::with_name("lot")
+            .long("--lot")
+            .help("Add a list of captioned tables with page numbers at the beginning of the document")
+        )
+        .arg(
+            Arg::with_name("outline-depth")
+            .long("--outline-depth")
+            .takes_value(true)
+            .help("Deepest heading level included in the PDF outline (bookmarks), level 5 and 6 headings are never included as wkhtmltopdf only supports up to 4 [Default: deepest heading in document]")
+            .possible_values(&["1", "2", "3", "4"])
+        )
         .arg(
             Arg::with_name("title")
             .long("--title")
@@ -131,4 +374,12 @@ pub fn app() -> App<'static, 'static> {
             SubCommand::with_name("changelog")
             .about("Print changelog for current build")
         )
+        .subcommand(
+            SubCommand::with_name("themes")
+            .about("Manage themes")
+            .subcommand(
+                SubCommand::with_name("list")
+                .about("List built-in and user themes")
+            )
+        )
 }
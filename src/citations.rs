@@ -0,0 +1,705 @@
+use crate::frontmatter::FrontMatter;
+use crate::sandbox::Sandbox;
+use crate::{error, warning};
+use clap::ArgMatches;
+use pulldown_cmark::escape::{escape_href, escape_html};
+use pulldown_cmark::{CowStr, Event};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs::read_to_string;
+use std::path::PathBuf;
+
+/* Citations: [@key] references resolved against a bibliography (--bibliography) in
+BibTeX or CSL-JSON format, the cited entries are listed in a references section at the end */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    Numeric,
+    AuthorYear,
+}
+
+pub struct Entry {
+    authors: Vec<(String, String)>, // family and given names
+    year: String,
+    title: String,
+    container: String, // journal or book the entry is part of
+    volume: String,
+    pages: String,
+    publisher: String,
+    link: Option<String>, // doi or url
+}
+
+pub struct Bibliography {
+    pub style: Style,
+    entries: HashMap<String, Entry>,
+}
+
+impl Bibliography {
+    // the cli options take precedence over 'bibliography' and 'citation-style' in the front matter
+    // a bibliography of the front matter is relative to the document and has to be in the sandbox
+    pub fn parse(
+        matches: &ArgMatches,
+        front_matter: &FrontMatter,
+        sandbox: &Sandbox,
+    ) -> Option<Bibliography> {
+        let (raw_path, path) = match matches.value_of("bibliography") {
+            Some(raw_path) => (raw_path, PathBuf::from(raw_path)),
+            None => {
+                let raw_path = front_matter.get("bibliography")?;
+                let path = sandbox.base().join(raw_path);
+                if path.exists() && !sandbox.allows(&path) {
+                    error(format!(
+                        "Bibliography {} is outside of the allowed directories (see --allow-path)",
+                        raw_path
+                    ));
+                }
+                (raw_path, path)
+            }
+        };
+
+        let style = match matches
+            .value_of("citation-style")
+            .or_else(|| front_matter.get("citation-style"))
+            .unwrap_or("numeric")
+        {
+            "numeric" => Style::Numeric,
+            "author-year" => Style::AuthorYear,
+            style => error(format!(
+                "Unknown citation style '{}'. Possible styles: numeric, author-year",
+                style
+            )),
+        };
+
+        let entries = match read_to_string(&path) {
+            Ok(source) => {
+                let json = path.extension() == Some(OsStr::new("json"))
+                    || source.trim_start().starts_with('[');
+                if json {
+                    parse_csl_json(&source)
+                } else {
+                    parse_bibtex(&source)
+                }
+            }
+            Err(e) => Err(e.to_string()),
+        };
+
+        match entries {
+            Ok(entries) => Some(Bibliography { style, entries }),
+            Err(e) => error(format!("Failed to load bibliography {}: {}", raw_path, e)),
+        }
+    }
+}
+
+// a single citation of a group, e.g. 'see @doe99, p. 33'
+struct Item<'a> {
+    prefix: &'a str,
+    key: &'a str,
+    locator: &'a str,
+}
+
+// splits the content of brackets into citations, None if it isn't a citation group
+fn parse_group(content: &str) -> Option<Vec<Item<'_>>> {
+    let mut items = Vec::new();
+
+    for raw in content.split(';') {
+        let at = raw.find('@')?;
+        // e.g. mail addresses aren't citations
+        if !raw[..at].is_empty() && !raw[..at].ends_with(char::is_whitespace) {
+            return None;
+        }
+        let rest = &raw[at + 1..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || "_-:./".contains(c)))
+            .unwrap_or(rest.len());
+        // trailing punctuation isn't part of the key
+        let key = rest[..end].trim_end_matches(&['.', ':'][..]);
+        if key.is_empty() {
+            return None;
+        }
+
+        items.push(Item {
+            prefix: raw[..at].trim(),
+            key,
+            locator: rest[key.len()..].trim().trim_start_matches(',').trim(),
+        });
+    }
+
+    Some(items)
+}
+
+pub struct Citations<'a> {
+    bibliography: &'a Bibliography,
+    numbers: HashMap<String, usize>, // number of each cited key
+    cited: Vec<String>,              // keys in order of their first citation
+}
+
+impl<'a> Citations<'a> {
+    pub fn new(bibliography: &'a Bibliography) -> Citations<'a> {
+        Citations {
+            bibliography,
+            numbers: HashMap::new(),
+            cited: Vec::new(),
+        }
+    }
+
+    // html for a text with citation groups ([@key], [see @a, p. 3; @b]), the text
+    // around them is rendered by escape
+    pub fn cite(&mut self, text: &str, escape: &dyn Fn(&str) -> String) -> String {
+        let mut html = String::new();
+        let mut rest = text;
+
+        while let Some(start) = rest.find('[') {
+            let group = rest[start + 1..]
+                .find(&['[', ']'][..])
+                .filter(|end| rest[start + 1 + end..].starts_with(']'))
+                .and_then(|end| {
+                    parse_group(&rest[start + 1..start + 1 + end]).map(|items| (end, items))
+                });
+
+            match group {
+                Some((end, items)) => {
+                    html.push_str(&escape(&rest[..start]));
+                    html.push_str(&self.group(&items));
+                    rest = &rest[start + end + 2..];
+                }
+                None => {
+                    html.push_str(&escape(&rest[..start + 1]));
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+
+        html.push_str(&escape(rest));
+        html
+    }
+
+    fn group(&mut self, items: &[Item]) -> String {
+        let rendered: Vec<String> = items.iter().map(|item| self.item(item)).collect();
+
+        match self.bibliography.style {
+            Style::Numeric => {
+                let separator = if items
+                    .iter()
+                    .any(|item| !item.prefix.is_empty() || !item.locator.is_empty())
+                {
+                    "; "
+                } else {
+                    ", "
+                };
+                format!(
+                    "<span class=\"citation\">[{}]</span>",
+                    rendered.join(separator)
+                )
+            }
+            Style::AuthorYear => {
+                format!("<span class=\"citation\">({})</span>", rendered.join("; "))
+            }
+        }
+    }
+
+    fn item(&mut self, item: &Item) -> String {
+        let mut html = String::new();
+        if !item.prefix.is_empty() {
+            escape_html(&mut html, item.prefix).unwrap();
+            html.push(' ');
+        }
+
+        match self.bibliography.entries.get(item.key) {
+            Some(entry) => {
+                if !self.numbers.contains_key(item.key) {
+                    self.cited.push(item.key.to_owned());
+                    self.numbers.insert(item.key.to_owned(), self.cited.len());
+                }
+
+                let label = match self.bibliography.style {
+                    Style::Numeric => self.numbers[item.key].to_string(),
+                    Style::AuthorYear => format!("{} {}", entry.short_authors(), entry.year()),
+                };
+                html.push_str("<a href=\"#");
+                escape_href(&mut html, &reference_id(item.key)).unwrap();
+                html.push_str("\">");
+                escape_html(&mut html, &label).unwrap();
+                html.push_str("</a>");
+            }
+            None => {
+                warning(format!(
+                    "Citation [@{}] wasn't found in the bibliography",
+                    item.key
+                ));
+                html.push_str("<strong>?");
+                escape_html(&mut html, item.key).unwrap();
+                html.push_str("</strong>");
+            }
+        }
+
+        if !item.locator.is_empty() {
+            html.push_str(", ");
+            escape_html(&mut html, item.locator).unwrap();
+        }
+        html
+    }
+
+    // adds the references section with all cited entries to the document events
+    pub fn finish(self, events: &mut Vec<Event>) {
+        if self.cited.is_empty() {
+            return;
+        }
+
+        let entries = &self.bibliography.entries;
+        let mut keys: Vec<&String> = self.cited.iter().collect();
+        if self.bibliography.style == Style::AuthorYear {
+            keys.sort_by_cached_key(|key| {
+                let entry = &entries[*key];
+                (
+                    entry.short_authors().to_lowercase(),
+                    entry.year().to_owned(),
+                )
+            });
+        }
+
+        let mut html = String::from("<div class=\"references\">");
+        for key in keys {
+            html.push_str("<div class=\"reference\" id=\"");
+            escape_html(&mut html, &reference_id(key)).unwrap();
+            html.push_str("\">");
+            if self.bibliography.style == Style::Numeric {
+                html.push_str(&format!(
+                    "<span class=\"reference-label\">[{}]</span>",
+                    self.numbers[key]
+                ));
+            }
+            html.push_str(&entries[key].to_html());
+            html.push_str("</div>");
+        }
+        html.push_str("</div>\n");
+
+        events.push(Event::Html(CowStr::from(html)));
+    }
+}
+
+fn reference_id(key: &str) -> String {
+    format!("ref-{}", key)
+}
+
+impl Entry {
+    // authors for author-year citations, e.g. 'Doe', 'Doe & Smith' or 'Doe et al.'
+    fn short_authors(&self) -> String {
+        match self.authors.as_slice() {
+            [] => self.title.clone(),
+            [(first, _)] => first.clone(),
+            [(first, _), (second, _)] => format!("{} & {}", first, second),
+            [(first, _), ..] => format!("{} et al.", first),
+        }
+    }
+
+    fn year(&self) -> &str {
+        if self.year.is_empty() {
+            "n.d."
+        } else {
+            &self.year
+        }
+    }
+
+    // e.g. 'Doe, John; Smith, Anna (2020). Title. <em>Journal</em>, 12, 33–45. Publisher. link'
+    fn to_html(&self) -> String {
+        let mut html = String::from("<p>");
+
+        let authors: Vec<String> = self
+            .authors
+            .iter()
+            .map(|(family, given)| {
+                if given.is_empty() {
+                    family.clone()
+                } else {
+                    format!("{}, {}", family, given)
+                }
+            })
+            .collect();
+        if !authors.is_empty() {
+            escape_html(&mut html, &authors.join("; ")).unwrap();
+            html.push(' ');
+        }
+        html.push('(');
+        escape_html(&mut html, self.year()).unwrap();
+        html.push_str("). ");
+
+        if !self.title.is_empty() {
+            escape_html(&mut html, self.title.trim_end_matches('.')).unwrap();
+            html.push_str(". ");
+        }
+
+        if !self.container.is_empty() {
+            html.push_str("<em>");
+            escape_html(&mut html, &self.container).unwrap();
+            html.push_str("</em>");
+            for detail in [&self.volume, &self.pages].iter() {
+                if !detail.is_empty() {
+                    html.push_str(", ");
+                    escape_html(&mut html, detail).unwrap();
+                }
+            }
+            html.push_str(". ");
+        }
+
+        if !self.publisher.is_empty() {
+            escape_html(&mut html, &self.publisher).unwrap();
+            html.push_str(". ");
+        }
+
+        if let Some(link) = &self.link {
+            html.push_str("<a href=\"");
+            escape_href(&mut html, link).unwrap();
+            html.push_str("\">");
+            escape_html(&mut html, link).unwrap();
+            html.push_str("</a>");
+        }
+
+        html.truncate(html.trim_end().len());
+        html.push_str("</p>");
+        html
+    }
+}
+
+/* CSL-JSON: an array of items with 'id', 'author', 'issued', 'title', ... */
+fn parse_csl_json(source: &str) -> Result<HashMap<String, Entry>, String> {
+    let items = match serde_json::from_str::<Value>(source).map_err(|e| e.to_string())? {
+        Value::Array(items) => items,
+        _ => return Err("expected an array of CSL-JSON items".to_owned()),
+    };
+
+    let mut entries = HashMap::new();
+    for item in items {
+        let text = |name: &str| match item.get(name) {
+            Some(Value::String(text)) => text.trim().to_owned(),
+            Some(Value::Number(number)) => number.to_string(),
+            _ => String::new(),
+        };
+
+        let id = text("id");
+        if id.is_empty() {
+            return Err("an item has no id".to_owned());
+        }
+
+        let names = match item.get("author").or_else(|| item.get("editor")) {
+            Some(Value::Array(names)) => names.clone(),
+            _ => Vec::new(),
+        };
+        let authors = names
+            .iter()
+            .map(|name| {
+                let part = |key: &str| name.get(key).and_then(Value::as_str).unwrap_or("");
+                if part("family").is_empty() {
+                    (part("literal").to_owned(), String::new())
+                } else {
+                    (part("family").to_owned(), part("given").to_owned())
+                }
+            })
+            .filter(|(family, _)| !family.is_empty())
+            .collect();
+
+        // 'issued' is either {"date-parts": [[2020, 1]]} or {"raw": "2020-01"}
+        let issued = item.get("issued");
+        let year = match issued.and_then(|issued| issued.pointer("/date-parts/0/0")) {
+            Some(Value::Number(year)) => year.to_string(),
+            Some(Value::String(year)) => year.clone(),
+            _ => issued
+                .and_then(|issued| issued.get("raw").or_else(|| issued.get("literal")))
+                .and_then(Value::as_str)
+                .map(|raw| raw.chars().take(4).collect())
+                .unwrap_or_default(),
+        };
+
+        let doi = text("DOI");
+        let url = text("URL");
+        let link = if !doi.is_empty() {
+            Some(format!("https://doi.org/{}", doi))
+        } else if !url.is_empty() {
+            Some(url)
+        } else {
+            None
+        };
+
+        let entry = Entry {
+            authors,
+            year,
+            title: text("title"),
+            container: text("container-title"),
+            volume: text("volume"),
+            pages: text("page").replace('-', "–"),
+            publisher: text("publisher"),
+            link,
+        };
+        if entries.insert(id.clone(), entry).is_some() {
+            warning(format!(
+                "Bibliography entry '{}' is defined more than once",
+                id
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+/* BibTeX: @type{key, field = {value}, field = "value", field = 2020} */
+fn parse_bibtex(source: &str) -> Result<HashMap<String, Entry>, String> {
+    let mut entries = HashMap::new();
+    let mut macros = HashMap::new(); // @string definitions
+    let mut rest = source;
+
+    while let Some(start) = rest.find('@') {
+        rest = &rest[start + 1..];
+        let open = match rest.find(&['{', '('][..]) {
+            Some(open) => open,
+            None => break,
+        };
+        let kind = rest[..open].trim().to_lowercase();
+        let end = match closing(rest, open) {
+            Some(end) => end,
+            None => return Err(format!("@{} entry isn't closed", kind)),
+        };
+        let body = &rest[open + 1..end];
+        rest = &rest[end + 1..];
+
+        if kind == "string" {
+            macros.extend(parse_fields(body, &macros)?);
+            continue;
+        } else if kind == "comment" || kind == "preamble" {
+            continue;
+        }
+
+        let (key, fields) = match body.split_once(',') {
+            Some((key, fields)) => (key.trim(), parse_fields(fields, &macros)?),
+            None => (body.trim(), HashMap::new()),
+        };
+        if key.is_empty() {
+            return Err(format!("@{} entry without key", kind));
+        }
+
+        let field = |name: &str| {
+            fields
+                .get(name)
+                .map(|value| clean_latex(value))
+                .unwrap_or_default()
+        };
+        let names = fields
+            .get("author")
+            .or_else(|| fields.get("editor"))
+            .map(|names| split_names(names))
+            .unwrap_or_default();
+
+        let year = match field("year") {
+            year if year.is_empty() => field("date").chars().take(4).collect(),
+            year => year,
+        };
+        let link = match (field("doi"), field("url")) {
+            (doi, _) if !doi.is_empty() => Some(format!("https://doi.org/{}", doi)),
+            (_, url) if !url.is_empty() => Some(url),
+            _ => None,
+        };
+        let container = ["journal", "journaltitle", "booktitle"]
+            .iter()
+            .map(|name| field(name))
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+        let publisher = ["publisher", "institution", "school", "organization"]
+            .iter()
+            .map(|name| field(name))
+            .find(|value| !value.is_empty())
+            .unwrap_or_default();
+
+        let entry = Entry {
+            authors: names,
+            year,
+            title: field("title"),
+            container,
+            volume: field("volume"),
+            pages: field("pages"),
+            publisher,
+            link,
+        };
+        if entries.insert(key.to_owned(), entry).is_some() {
+            warning(format!(
+                "Bibliography entry '{}' is defined more than once",
+                key
+            ));
+        }
+    }
+
+    Ok(entries)
+}
+
+// index of the delimiter closing the one at open, braces are nested
+fn closing(source: &str, open: usize) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let close = if bytes[open] == b'(' { b')' } else { b'}' };
+    let mut depth = 0;
+
+    for (i, byte) in bytes.iter().enumerate().skip(open + 1) {
+        match *byte {
+            b'{' => depth += 1,
+            b'}' if depth > 0 => depth -= 1,
+            byte if byte == close && depth == 0 => return Some(i),
+            _ => (),
+        }
+    }
+    None
+}
+
+// fields of an entry with lowercase names and raw values (without delimiters)
+fn parse_fields(
+    source: &str,
+    macros: &HashMap<String, String>,
+) -> Result<HashMap<String, String>, String> {
+    let mut fields = HashMap::new();
+    let bytes = source.as_bytes();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let equals = match source[i..].find('=') {
+            Some(equals) => i + equals,
+            None if source[i..]
+                .trim_matches(|c: char| c == ',' || c.is_whitespace())
+                .is_empty() =>
+            {
+                break
+            }
+            None => return Err(format!("field '{}' has no value", source[i..].trim())),
+        };
+        let name = source[i..equals]
+            .trim_matches(|c: char| c == ',' || c.is_whitespace())
+            .to_lowercase();
+        i = equals + 1;
+
+        // a value may be concatenated from multiple parts with '#'
+        let mut value = String::new();
+        loop {
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            match bytes.get(i) {
+                Some(b'{') => {
+                    let end = closing(source, i)
+                        .ok_or_else(|| format!("field '{}' isn't closed", name))?;
+                    value.push_str(&source[i + 1..end]);
+                    i = end + 1;
+                }
+                Some(b'"') => {
+                    let mut depth = 0;
+                    let mut end = i + 1;
+                    while end < bytes.len() && !(bytes[end] == b'"' && depth == 0) {
+                        match bytes[end] {
+                            b'{' => depth += 1,
+                            b'}' => depth -= 1,
+                            _ => (),
+                        }
+                        end += 1;
+                    }
+                    if end == bytes.len() {
+                        return Err(format!("field '{}' isn't closed", name));
+                    }
+                    value.push_str(&source[i + 1..end]);
+                    i = end + 1;
+                }
+                // numbers and @string macros, unknown macros are used as is
+                _ => {
+                    let end = source[i..]
+                        .find(&[',', '#'][..])
+                        .map_or(bytes.len(), |end| i + end);
+                    let word = source[i..end].trim();
+                    match macros.get(&word.to_lowercase()) {
+                        Some(expanded) => value.push_str(expanded),
+                        None => value.push_str(word),
+                    }
+                    i = end;
+                }
+            }
+
+            while i < bytes.len() && bytes[i].is_ascii_whitespace() {
+                i += 1;
+            }
+            if bytes.get(i) == Some(&b'#') {
+                i += 1;
+            } else {
+                break;
+            }
+        }
+
+        fields.insert(name, value);
+    }
+
+    Ok(fields)
+}
+
+// splits 'Doe, John and Anna Smith and {World Health Organization}' into family and given names
+fn split_names(names: &str) -> Vec<(String, String)> {
+    let mut split = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0;
+
+    for word in names.split_whitespace() {
+        if word == "and" && depth == 0 {
+            split.push(current.clone());
+            current.clear();
+            continue;
+        }
+
+        depth += word.matches('{').count() as i32 - word.matches('}').count() as i32;
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(word);
+    }
+    split.push(current);
+
+    split
+        .iter()
+        .filter(|name| !name.is_empty())
+        .map(|name| {
+            // names in braces are kept as is (e.g. organizations)
+            if name.starts_with('{') && closing(name, 0) == Some(name.len() - 1) {
+                return (clean_latex(name), String::new());
+            }
+
+            match name.split_once(',') {
+                Some((family, given)) => (clean_latex(family), clean_latex(given)),
+                None => match name.rsplit_once(' ') {
+                    Some((given, family)) => (clean_latex(family), clean_latex(given)),
+                    None => (clean_latex(name), String::new()),
+                },
+            }
+        })
+        .collect()
+}
+
+// removes braces and simple latex commands, e.g. '{T}he \& --' becomes 'The & –'
+fn clean_latex(value: &str) -> String {
+    let mut text = String::new();
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' | '}' => (),
+            '~' => text.push('\u{a0}'),
+            '\\' => match chars.next() {
+                Some(escaped) if "&%$#_{}".contains(escaped) => text.push(escaped),
+                // the name of a command is dropped, e.g. \textit{...} or \"a
+                Some(command) if command.is_alphabetic() => {
+                    while chars.peek().filter(|c| c.is_alphabetic()).is_some() {
+                        chars.next();
+                    }
+                    if chars.peek() == Some(&' ') {
+                        chars.next();
+                    }
+                }
+                _ => (),
+            },
+            c => text.push(c),
+        }
+    }
+
+    text.split_whitespace()
+        .collect::<Vec<&str>>()
+        .join(" ")
+        .replace("---", "—")
+        .replace("--", "–")
+}
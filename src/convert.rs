@@ -1,29 +1,190 @@
-use crate::document::{Document, Footer};
+use crate::document::{Artifacts, Document, Footer, PageHeader, Rendered};
+use crate::locale::Catalog;
+use crate::outline;
+use crate::print::{impose, PrintOptions};
+use crate::sandbox::Sandbox;
+use crate::style::Theme;
 use crate::{error, info, warning};
 use clap::ArgMatches;
 use pulldown_cmark::Options;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::write;
+use std::io::Read;
 use std::process::exit;
-use wkhtmltopdf::{Orientation, PageSize, PdfApplication, Size};
+use wkhtmltopdf::{Orientation, PdfApplication, PdfOutput};
 
 #[cfg(target_os = "windows")]
 static EXIT_CODE: i32 = 0x0100;
 #[cfg(not(target_os = "windows"))]
 static EXIT_CODE: i32 = 0;
 
-fn parse_pagesize(wrapped: Option<&str>) -> PageSize {
+// page sizes supported by wkhtmltopdf
+static PAGE_SIZES: &[&str] = &[
+    "A0",
+    "A1",
+    "A2",
+    "A3",
+    "A4",
+    "A5",
+    "A6",
+    "A7",
+    "A8",
+    "A9",
+    "B0",
+    "B1",
+    "B2",
+    "B3",
+    "B4",
+    "B5",
+    "B6",
+    "B7",
+    "B8",
+    "B9",
+    "B10",
+    "C5E",
+    "Comm10E",
+    "DLE",
+    "Executive",
+    "Folio",
+    "Ledger",
+    "Legal",
+    "Letter",
+    "Tabloid",
+];
+
+// named page size as understood by wkhtmltopdf (case insensitive)
+fn parse_pagesize(wrapped: Option<&str>) -> &'static str {
+    let size = wrapped.unwrap_or("A4");
+    match PAGE_SIZES
+        .iter()
+        .find(|name| name.eq_ignore_ascii_case(size))
+    {
+        Some(name) => name,
+        None => error(format!(
+            "Unknown page size '{}'. Possible page sizes: {}",
+            size,
+            PAGE_SIZES.join(", ")
+        )),
+    }
+}
+
+// length with unit for wkhtmltopdf (e.g. 12.5mm, 2cm or 1in)
+#[derive(Clone, Copy)]
+pub struct Length {
+    pub value: f64,
+    pub unit: &'static str,
+}
+
+impl Length {
+    pub fn millimetres(&self) -> f64 {
+        match self.unit {
+            "cm" => self.value * 10.0,
+            "in" => self.value * 25.4,
+            _ => self.value,
+        }
+    }
+
+    pub fn points(&self) -> f64 {
+        self.millimetres() * 72.0 / 25.4
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "{}{}", self.value, self.unit)
+    }
+}
+
+// parses a length, plain numbers are millimetres
+pub fn parse_length(option: &str, value: &str) -> Length {
+    let value = value.trim();
+    let split = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(split);
+    let unit = match unit.trim() {
+        "" | "mm" => "mm",
+        "cm" => "cm",
+        "in" => "in",
+        _ => "",
+    };
+
+    match number.parse::<f64>() {
+        Ok(number) if number.is_finite() && !unit.is_empty() => Length {
+            value: number,
+            unit,
+        },
+        _ => error(format!(
+            "Invalid length '{}' for --{}: expected a number with the unit mm, cm or in (e.g. 12.5mm)",
+            value, option
+        )),
+    }
+}
+
+// saves the generated PDF, in print mode it's imposed for duplex printing before
+fn save(out: &mut PdfOutput, print: Option<&PrintOptions>, outline_depth: u32, path: &str) {
+    // the PDF is only post-processed for print mode or a shallower outline
+    let result = if print.is_none() && outline_depth >= outline::GENERATED_DEPTH {
+        out.save(path).map(|_| ())
+    } else {
+        let mut pdf = Vec::new();
+        if let Err(e) = out.read_to_end(&mut pdf) {
+            error(format!("Failed to read generated PDF: {}", e));
+        }
+
+        if let Some(options) = print {
+            pdf = match impose(&pdf, options) {
+                Ok(imposed) => imposed,
+                Err(e) => error(format!("Failed to apply print mode: {}", e)),
+            };
+        }
+
+        if outline_depth < outline::GENERATED_DEPTH {
+            pdf = match outline::limit(&pdf, outline_depth) {
+                Ok(limited) => limited,
+                Err(e) => error(format!("Failed to limit the outline depth: {}", e)),
+            };
+        }
+
+        write(path, pdf)
+    };
+
+    match result {
+        Ok(_) => info(format!("Generated PDF and saved to {}", path)),
+        Err(e) => error(format!("Failed to save PDF to {}: {}", path, e)),
+    };
+}
+
+// outline depth for the PDF bookmarks, defaults to the deepest heading in the document
+fn parse_outline_depth(wrapped: Option<&str>, document_depth: u32) -> u32 {
     match wrapped {
-        Some(size) => match size {
-            "A3" => PageSize::A3,
-            "A4" => PageSize::A4,
-            "A5" => PageSize::A5,
-            "A6" => PageSize::A6,
-            _ => PageSize::A4,
+        Some(depth) => match depth.parse::<u32>() {
+            Ok(depth) if (1..=outline::GENERATED_DEPTH).contains(&depth) => depth,
+            _ => error(format!(
+                "Invalid outline depth '{}': expected a heading level from 1 to 4",
+                depth
+            )),
         },
-        None => PageSize::A4,
+        // wkhtmltopdf can't add level 5 and 6 headings to the outline
+        None if document_depth > outline::GENERATED_DEPTH => {
+            warning(format!(
+                "The PDF outline only contains headings up to level {}, deeper headings are left out",
+                outline::GENERATED_DEPTH
+            ));
+            outline::GENERATED_DEPTH
+        }
+        None => document_depth,
     }
 }
 
-pub fn convert(html: String, name: Option<String>, matches: &ArgMatches) -> ! {
+pub fn convert(
+    document: Rendered,
+    name: Option<String>,
+    catalog: &Catalog,
+    theme: &Theme,
+    sandbox: &Sandbox,
+    matches: &ArgMatches,
+) -> ! {
     // create pdf application
     // this may initialize wkhtml too
     let mut app = match PdfApplication::new() {
@@ -43,15 +204,34 @@ pub fn convert(html: String, name: Option<String>, matches: &ArgMatches) -> ! {
 
     // margin is not really important but may be useful when you intend to e.g. print a PDF later
     // on and want to put it into a folder. Useful for handouts too
-    let margin = match matches.value_of("margin") {
-        Some(margin) => match margin.parse::<u32>() {
-            Ok(margin) => Size::Millimeters(margin),
-            Err(_) => {
-                info("Invalid Margin supplied");
-                Size::Millimeters(10)
-            }
-        },
-        None => Size::Millimeters(10),
+    // --margin applies to all sides and may be overridden per side
+    let margin = parse_length("margin", matches.value_of("margin").unwrap_or("10mm"));
+    let side = |option: &str| match matches.value_of(option) {
+        Some(value) => parse_length(option, value),
+        None => margin,
+    };
+    let (top, right, bottom, mut left) = (
+        side("margin-top"),
+        side("margin-right"),
+        side("margin-bottom"),
+        side("margin-left"),
+    );
+
+    // print mode renders every page with the gutter on the left (inner side of odd pages)
+    // and mirrors it on even pages afterwards
+    let print = if matches.is_present("print") {
+        let gutter = parse_length("gutter", matches.value_of("gutter").unwrap_or("15mm"));
+        left = Length {
+            value: left.millimetres() + gutter.millimetres(),
+            unit: "mm",
+        };
+
+        Some(PrintOptions {
+            gutter: gutter.points(),
+            crop_marks: matches.is_present("crop-marks"),
+        })
+    } else {
+        None
     };
 
     // Not too sure if I may extend this part with support for angles
@@ -64,80 +244,108 @@ pub fn convert(html: String, name: Option<String>, matches: &ArgMatches) -> ! {
         None => Orientation::Portrait,
     };
 
+    // footer, header and the kept document are written into a private directory of this run
+    let artifacts = Artifacts::new();
+
     let mut builder = app.builder();
 
-    // save has_name & footer_path here to make the available later
-    let has_name = name.is_some();
+    // page size is either named or custom with --page-width and --page-height
+    match (
+        matches.value_of("page-width"),
+        matches.value_of("page-height"),
+    ) {
+        (Some(width), Some(height)) => unsafe {
+            builder
+                .global_setting("size.width", parse_length("page-width", width).to_string())
+                .global_setting(
+                    "size.height",
+                    parse_length("page-height", height).to_string(),
+                )
+        },
+        _ => unsafe {
+            builder.global_setting(
+                "size.pageSize",
+                parse_pagesize(matches.value_of("pagesize")),
+            )
+        },
+    };
 
-    if has_name {
-        let footer = Footer::new(name.unwrap(), matches);
-        let footer_path = match footer.to_file() {
-            Ok(path) => path,
-            Err(e) => error(format!("Failed to render footer: {}", e)),
-        };
+    unsafe {
+        builder
+            .global_setting("margin.top", top.to_string())
+            .global_setting("margin.right", right.to_string())
+            .global_setting("margin.bottom", bottom.to_string())
+            .global_setting("margin.left", left.to_string())
+    };
 
-        if matches.is_present("print") {
-            unsafe { builder.object_setting("margin.left", "12cm") };
-        }
+    // wkhtmltopdf builds the outline (bookmarks) from the h1-h6 elements of the document,
+    // its depth is limited after rendering
+    let outline_depth = parse_outline_depth(matches.value_of("outline-depth"), document.depth);
+    builder.outline(Some(outline_depth));
 
-        let out_result = unsafe {
-            builder
-                .orientation(orientation)
-                .object_setting("footer.htmlUrl", footer_path) // pretty sure this isn't totally safe
-                .object_setting("load.blockLocalFileAccess", "false")
-                .object_setting("web.enableJavascript", "true")
-                .margin(margin)
-                .page_size(parse_pagesize(matches.value_of("pagesize")))
-                .title(&title)
-                .build_from_html(&html)
-        };
+    // wkhtmltopdf doesn't load any local files, images from the allowed directories are
+    // embedded into the document before. JavaScript is opt-in, internal links (#anchor) stay links
+    unsafe {
+        builder
+            .object_setting("load.blockLocalFileAccess", "true")
+            .object_setting("web.enableJavascript", sandbox.javascript.to_string())
+            .object_setting("useLocalLinks", "true")
+    };
 
-        let mut out = match out_result {
-            Ok(pdf) => pdf,
-            Err(e) => error(format!("Failed to generate PDF: {}", e)),
-        };
+    // with --remote=deny wkhtmltopdf may not load remote resources either (e.g. from raw html),
+    // there is no setting for this so all requests go to a proxy that doesn't exist
+    if !sandbox.network {
+        unsafe { builder.object_setting("load.proxy", "http://127.0.0.1:9") };
+    }
 
-        let path = matches.value_of("OUTPUT").unwrap();
-        match out.save(path) {
-            Ok(_) => info(format!("Generated PDF and saved to {}", path)),
-            Err(e) => error(format!("Failed to save PDF to {}: {}", path, e)),
+    // page header from the theme package
+    if let Some(template) = &theme.header {
+        let header = PageHeader {
+            title: title.to_owned(),
         };
-    } else {
-        if matches.is_present("print") {
-            unsafe { builder.object_setting("margin.left", "12cm") };
-        }
-
-        let out_result = unsafe {
-            builder
-                .orientation(orientation)
-                .margin(margin)
-                .page_size(parse_pagesize(matches.value_of("pagesize")))
-                .object_setting("load.blockLocalFileAccess", "false")
-                .object_setting("web.enableJavascript", "true")
-                .title(&title)
-                .build_from_html(&html)
+        match header.to_file(template, &artifacts) {
+            Ok(path) => unsafe { builder.object_setting("header.htmlUrl", path) },
+            Err(e) => error(format!("Failed to render header: {}", e)),
         };
+    }
 
-        let mut out = match out_result {
-            Ok(pdf) => pdf,
-            Err(e) => error(format!("Failed to generate PDF: {}", e)),
+    if let Some(name) = name {
+        let footer = Footer::new(name, catalog, matches);
+        let footer_path = match footer.to_file(theme, &artifacts) {
+            Ok(path) => path,
+            Err(e) => error(format!("Failed to render footer: {}", e)),
         };
 
-        let path = matches.value_of("OUTPUT").unwrap();
-        match out.save(path) {
-            Ok(_) => info(format!("Generated PDF and saved to {}", path)),
-            Err(e) => error(format!("Failed to save PDF to {}: {}", path, e)),
-        };
+        unsafe { builder.object_setting("footer.htmlUrl", footer_path) }; // pretty sure this isn't totally safe
     }
 
+    builder.orientation(orientation).title(&title);
+
+    // page numbers of the lists of figures and tables need a first rendering
+    let html = document
+        .lists
+        .paginate(&mut builder, document.html, print.as_ref());
+
+    let mut out = match builder.build_from_html(&html) {
+        Ok(pdf) => pdf,
+        Err(e) => error(format!("Failed to generate PDF: {}", e)),
+    };
+
+    save(
+        &mut out,
+        print.as_ref(),
+        outline_depth,
+        matches.value_of("OUTPUT").unwrap(),
+    );
+
     if !matches.is_present("keep") {
-        match Document::remove_artifacts() {
-            Ok(_) => (),
-            Err(e) => warning(format!("Failed to remove old document artifact: {}", e)),
-        };
+        Artifacts::cleanup();
     } else {
-        match Document::to_file(html) {
-            Ok(path) => info(format!("Kept document body under: {}", path)),
+        match Document::to_file(html, &artifacts) {
+            Ok(_) => info(format!(
+                "Kept temporary files under: {}",
+                artifacts.keep().display()
+            )),
             Err(e) => error(format!(
                 "Failed to render tmp document (try without -k): {}",
                 e
@@ -153,8 +361,7 @@ pub fn build_options(matches: &ArgMatches) -> Options {
 
     if matches.is_present("extensions") {
         // extract data and prepare new options obj
-        let activated_extensions: Vec<&str> =
-            matches.value_of("extensions").unwrap().split(",").collect();
+        let activated_extensions: Vec<&str> = matches.values_of("extensions").unwrap().collect();
         // check for extensions
 
         // check for strikethrough extension
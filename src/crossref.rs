@@ -0,0 +1,301 @@
+use crate::error;
+use crate::locale::Catalog;
+use pulldown_cmark::escape::{escape_href, escape_html};
+use std::collections::HashMap;
+
+/* Cross-references: numbered sections, figures and tables with labels ({#sec:results})
+and references to them (@sec:results, @fig:arch, @tbl:costs) resolved after rendering */
+pub struct CrossReferences {
+    numbering: bool,                 // --number-sections
+    sections: Vec<u32>,              // counter for each heading level
+    pub figures: Vec<Caption>,       // all figures in order, e.g. for --lof
+    pub tables: Vec<Caption>,        // all captioned tables in order, e.g. for --lot
+    labels: HashMap<String, String>, // text of references to each label
+}
+
+// numbered caption of a figure or table with the id of its anchor
+pub struct Caption {
+    pub id: String,
+    pub text: String,
+}
+
+// GitHub compatible id for a heading, e.g. 'Set up & run' becomes 'set-up--run'
+pub fn slugify(title: &str) -> String {
+    title
+        .trim()
+        .to_lowercase()
+        .chars()
+        .filter_map(|c| match c {
+            ' ' => Some('-'),
+            c if c.is_alphanumeric() || c == '-' || c == '_' => Some(c),
+            _ => None,
+        })
+        .collect()
+}
+
+// label prefixes that may be referenced
+static PREFIXES: &[&str] = &["sec:", "fig:", "tbl:"];
+
+// references are marked in the text and replaced once all labels are known
+static MARK_START: char = '\u{1}';
+static MARK_END: char = '\u{2}';
+
+// attributes of headings, figures and table captions, e.g. '#sec:intro -'
+pub struct Attributes {
+    pub id: Option<String>,
+    pub unnumbered: bool,
+    pub rest: Vec<String>,
+}
+
+// id, class, unnumbered marker or key=value pair, e.g. '#sec:intro', '.wide', '-', 'width=50%'
+fn is_attribute(token: &str) -> bool {
+    token.starts_with(&['#', '.', '-'][..])
+        || matches!(token.find('='), Some(equals) if equals > 0 && equals + 1 < token.len())
+}
+
+impl Attributes {
+    pub fn parse(attributes: &str) -> Attributes {
+        let mut parsed = Attributes {
+            id: None,
+            unnumbered: false,
+            rest: Vec::new(),
+        };
+
+        for attribute in attributes.split_whitespace() {
+            match attribute {
+                "-" | ".unnumbered" => parsed.unnumbered = true,
+                id if id.starts_with('#') && id.len() > 1 => parsed.id = Some(id[1..].to_owned()),
+                rest => parsed.rest.push(rest.to_owned()),
+            }
+        }
+
+        parsed
+    }
+
+    // splits trailing attributes from a text, e.g. 'Results {#sec:results}'
+    pub fn split(text: &str) -> Option<(&str, Attributes)> {
+        let trimmed = text.trim_end();
+        if !trimmed.ends_with('}') {
+            return None;
+        }
+
+        let start = trimmed.rfind('{')?;
+        let attributes = &trimmed[start + 1..trimmed.len() - 1];
+        // literal braces such as 'Sets {a, b}' stay part of the text
+        if attributes.trim().is_empty() || !attributes.split_whitespace().all(is_attribute) {
+            return None;
+        }
+        Some((&trimmed[..start], Attributes::parse(attributes)))
+    }
+}
+
+impl CrossReferences {
+    pub fn new(numbering: bool) -> CrossReferences {
+        CrossReferences {
+            numbering,
+            sections: Vec::new(),
+            figures: Vec::new(),
+            tables: Vec::new(),
+            labels: HashMap::new(),
+        }
+    }
+
+    fn define(&mut self, label: &str, text: String) {
+        if self.labels.insert(label.to_owned(), text).is_some() {
+            error(format!("Label {{#{}}} is used more than once", label));
+        }
+    }
+
+    // unique id for a heading, ids already in use get a counter appended (setup-1, setup-2, ...)
+    pub fn slug(&self, title: &str) -> String {
+        let mut base = slugify(title);
+        if base.is_empty() {
+            base = String::from("section");
+        }
+
+        let mut slug = base.clone();
+        let mut counter = 0;
+        while self.labels.contains_key(&slug) {
+            counter += 1;
+            slug = format!("{}-{}", base, counter);
+        }
+        slug
+    }
+
+    // number of a heading (e.g. '2.3') if sections are numbered
+    pub fn section(
+        &mut self,
+        level: u32,
+        title: &str,
+        attributes: &Attributes,
+        catalog: &Catalog,
+    ) -> Option<String> {
+        let number = if self.numbering && !attributes.unnumbered {
+            let level = level as usize;
+            self.sections.resize(level.max(self.sections.len()), 0);
+            self.sections[level - 1] += 1;
+            self.sections.truncate(level);
+
+            // leading levels without headings aren't part of the number (e.g. no h1)
+            let first = self.sections.iter().position(|n| *n > 0).unwrap_or(0);
+            let parts: Vec<String> = self.sections[first..]
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            Some(parts.join("."))
+        } else {
+            None
+        };
+
+        // unnumbered sections are referenced by their title
+        if let Some(id) = &attributes.id {
+            let text = match &number {
+                Some(number) => catalog.get("section-reference").replace("{number}", number),
+                None => title.trim().to_owned(),
+            };
+            self.define(id, text);
+        }
+
+        number
+    }
+
+    // numbered caption of the next figure, unlabeled figures get an id like figure-1
+    pub fn figure(&mut self, label: Option<&str>, title: &str, catalog: &Catalog) -> &Caption {
+        let number = (self.figures.len() + 1).to_string();
+        let caption = self.caption(label, "figure", &number, title, catalog);
+        self.figures.push(caption);
+        self.figures.last().unwrap()
+    }
+
+    // numbered caption of the next captioned table
+    pub fn table(&mut self, label: Option<&str>, title: &str, catalog: &Catalog) -> &Caption {
+        let number = (self.tables.len() + 1).to_string();
+        let caption = self.caption(label, "table", &number, title, catalog);
+        self.tables.push(caption);
+        self.tables.last().unwrap()
+    }
+
+    fn caption(
+        &mut self,
+        label: Option<&str>,
+        kind: &str,
+        number: &str,
+        title: &str,
+        catalog: &Catalog,
+    ) -> Caption {
+        let id = match label {
+            Some(label) => {
+                let text = catalog
+                    .get(&format!("{}-reference", kind))
+                    .replace("{number}", number);
+                self.define(label, text);
+                label.to_owned()
+            }
+            None => format!("{}-{}", kind, number),
+        };
+
+        Caption {
+            id,
+            text: catalog
+                .get(&format!("{}-caption", kind))
+                .replace("{number}", number)
+                .replace("{caption}", title),
+        }
+    }
+
+    // marks references (@sec:results or [@sec:results]) in a text, None if there are none
+    pub fn mark(text: &str) -> Option<String> {
+        if !text.contains('@') {
+            return None;
+        }
+
+        let mut marked = String::new();
+        let mut rest = text;
+        let mut found = false;
+
+        while let Some(at) = rest.find('@') {
+            let before = &rest[..at];
+            let after = &rest[at + 1..];
+            let end = after
+                .find(|c: char| !(c.is_alphanumeric() || "_-:.".contains(c)))
+                .unwrap_or(after.len());
+            let label = after[..end].trim_end_matches(&['.', ':'][..]);
+
+            // e.g. mail addresses aren't references
+            let valid = PREFIXES
+                .iter()
+                .any(|prefix| label.starts_with(prefix) && label.len() > prefix.len())
+                && !before.ends_with(char::is_alphanumeric);
+            if !valid {
+                marked.push_str(&rest[..at + 1]);
+                rest = after;
+                continue;
+            }
+
+            found = true;
+            let mut after_label = &after[label.len()..];
+            match (before.strip_suffix('['), after_label.strip_prefix(']')) {
+                (Some(before), Some(stripped)) => {
+                    marked.push_str(before);
+                    after_label = stripped;
+                }
+                _ => marked.push_str(before),
+            }
+            marked.push(MARK_START);
+            marked.push_str(label);
+            marked.push(MARK_END);
+            rest = after_label;
+        }
+
+        marked.push_str(rest);
+        if found {
+            Some(marked)
+        } else {
+            None
+        }
+    }
+
+    // replaces the marked references with links, dangling references are an error
+    pub fn resolve(&self, html: String) -> String {
+        if !html.contains(MARK_START) {
+            return html;
+        }
+
+        let mut resolved = String::new();
+        let mut dangling = Vec::new();
+        let mut rest = html.as_str();
+
+        while let Some(start) = rest.find(MARK_START) {
+            let end = match rest[start..].find(MARK_END) {
+                Some(end) => start + end,
+                None => break,
+            };
+            resolved.push_str(&rest[..start]);
+            let label = &rest[start + 1..end];
+
+            match self.labels.get(label) {
+                Some(text) => {
+                    resolved.push_str("<a class=\"cross-reference\" href=\"#");
+                    escape_href(&mut resolved, label).unwrap();
+                    resolved.push_str("\">");
+                    escape_html(&mut resolved, text).unwrap();
+                    resolved.push_str("</a>");
+                }
+                None => dangling.push(format!("@{}", label)),
+            }
+            rest = &rest[end + 1..];
+        }
+        resolved.push_str(rest);
+
+        if !dangling.is_empty() {
+            dangling.sort();
+            dangling.dedup();
+            error(format!(
+                "Unresolved cross-references (no heading, figure or table has these labels): {}",
+                dangling.join(", ")
+            ));
+        }
+
+        resolved
+    }
+}
@@ -1,11 +1,17 @@
-use crate::style::{Stylesheet, Themes};
-use crate::{error, info, warning};
+use crate::lists::Lists;
+use crate::locale::Catalog;
+use crate::style::{Stylesheet, Theme};
+use crate::{error, warning};
+use ammonia::clean_text;
 use askama::Template;
+use chrono::format::{Item, StrftimeItems};
 use chrono::prelude::*;
 use clap::ArgMatches;
-use std::fs::{read_dir, remove_file, File};
+use std::fs::{remove_dir_all, File};
 use std::io::{Error as IOError, Write};
-use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use tempfile::{Builder, TempDir};
 
 // trait for options
 pub trait ClapOption {
@@ -13,12 +19,6 @@ pub trait ClapOption {
     fn options() -> &'static [&'static str];
 }
 
-// Languages to support different locales
-pub enum Languages {
-    EN,
-    DE,
-}
-
 // Licenses to support direct attribution
 pub enum CC4Licenses {
     BY,
@@ -29,20 +29,6 @@ pub enum CC4Licenses {
 }
 
 /*  implementations for options */
-impl ClapOption for Languages {
-    fn parse(matches: &ArgMatches) -> Self {
-        if matches.is_present("de") {
-            Self::DE
-        } else {
-            Self::EN
-        }
-    }
-
-    fn options() -> &'static [&'static str] {
-        &["de", "en"]
-    }
-}
-
 impl ClapOption for CC4Licenses {
     fn parse(matches: &ArgMatches) -> Self {
         match matches.value_of("license") {
@@ -63,7 +49,7 @@ impl ClapOption for CC4Licenses {
 }
 
 impl CC4Licenses {
-    fn display(&self, language: &Languages) -> String {
+    fn display(&self, catalog: &Catalog) -> String {
         match self {
             Self::NONE => "".to_owned(),
             license => {
@@ -75,10 +61,10 @@ impl CC4Licenses {
                     CC4Licenses::NONE => unimplemented!(),
                 };
 
-                match *language {
-                    Languages::DE => format!("- Lizenziert unter {} 4.0", short),
-                    Languages::EN => format!("- Licensed under {} 4.0", short),
-                }
+                format!(
+                    "- {}",
+                    catalog.get("licensed-under").replace("{license}", short)
+                )
             }
         }
     }
@@ -89,7 +75,11 @@ impl CC4Licenses {
 pub struct Header {
     pub css: &'static str,
     pub theme: String,
-    pub local: String,
+    pub variables: String,
+    pub fonts: String,
+    pub columns: String,
+    pub text: String,
+    pub custom: Vec<String>,
 }
 
 #[derive(Template)]
@@ -101,18 +91,28 @@ pub struct Footer {
     pub license: String,
 }
 
+// Page header from a theme package template
+pub struct PageHeader {
+    pub title: String,
+}
+
 #[derive(Template)]
 #[template(path = "document.html")]
 pub struct Document {
+    pub lang: String,
     pub header: Header,
     pub content: String,
 }
 
 impl Header {
-    pub fn new(style: Stylesheet, theme: String, language: &Languages) -> Header {
+    pub fn new(style: Stylesheet, theme: String, catalog: &Catalog) -> Header {
         Header {
             css: style.main,
-            local: style.local(language),
+            text: Stylesheet::localized(catalog),
+            variables: style.variables,
+            fonts: style.fonts,
+            columns: style.columns,
+            custom: style.custom,
             theme,
         }
     }
@@ -138,58 +138,50 @@ impl Footer {
         }
     }
 
-    pub fn new(name: String, matches: &ArgMatches) -> Footer {
+    pub fn new(name: String, catalog: &Catalog, matches: &ArgMatches) -> Footer {
         let local = Self::parse_date(matches);
-        let language = Languages::parse(matches);
-        let (date, text) = match language {
-            Languages::EN => (
-                local
-                    .format_localized("%b %e, %Y", Locale::en_GB)
-                    .to_string(),
-                "Created by".to_owned(),
-            ),
-            Languages::DE => (
-                local
-                    .format_localized("%a, %e %b %Y", Locale::de_DE)
-                    .to_string(),
-                "Erstellt von".to_owned(),
-            ),
-        };
+        // chrono panics when formatting with an invalid specifier, e.g. from --translations
+        let format = catalog.get("date-format");
+        if StrftimeItems::new(format).any(|item| item == Item::Error) {
+            error(format!(
+                "Invalid date-format '{}' in the translations: expected a strftime format like '%b %e, %Y'",
+                format
+            ));
+        }
+        let date = local.format_localized(format, catalog.locale()).to_string();
 
         Footer {
             name,
             date,
-            text,
-            license: CC4Licenses::parse(&matches).display(&language),
+            text: catalog.get("created-by").to_owned(),
+            license: CC4Licenses::parse(&matches).display(catalog),
         }
     }
 
-    pub fn to_file(&self) -> Result<String, IOError> {
-        // check path
-        let mut raw_path = FOOTER_PATH.to_owned();
-        let mut path = Path::new(&raw_path);
-
-        // when path exists already fall back to {number} - path
-        if path.exists() {
-            let mut i = 1;
-
-            while path.exists() {
-                raw_path = format!("./.footer-{}.html", i);
-                path = Path::new(&raw_path);
-                i += 1;
-            }
-        }
+    pub fn to_file(&self, theme: &Theme, artifacts: &Artifacts) -> Result<String, IOError> {
+        let path = artifacts.path(FOOTER_NAME);
 
         // Create a file
-        let mut file = match File::create(path) {
+        let mut file = match File::create(&path) {
             Ok(file) => file,
             Err(e) => error(format!("Failed to create tmp file: {}", e)),
         };
 
-        // Render Footer template into String
-        let text = match self.render() {
-            Ok(text) => text,
-            Err(e) => error(format!("Couldn't render footer: {}", e)),
+        // Render Footer template (or the one of the theme package) into String
+        let text = match &theme.footer {
+            Some(template) => fill_template(
+                template,
+                &[
+                    ("text", clean_text(&self.text)),
+                    ("name", self.name.clone()),
+                    ("date", clean_text(&self.date)),
+                    ("license", clean_text(&self.license)),
+                ],
+            ),
+            None => match self.render() {
+                Ok(text) => text,
+                Err(e) => error(format!("Couldn't render footer: {}", e)),
+            },
         };
 
         // Write Footer to tmp file
@@ -198,15 +190,44 @@ impl Footer {
             Err(e) => error(format!("Failed to write to tmp file: {}", e)),
         };
 
-        Ok(raw_path)
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
+
+impl PageHeader {
+    pub fn to_file(&self, template: &str, artifacts: &Artifacts) -> Result<String, IOError> {
+        let path = artifacts.path(HEADER_NAME);
+
+        // Create a file
+        let mut file = match File::create(&path) {
+            Ok(file) => file,
+            Err(e) => error(format!("Failed to create tmp file: {}", e)),
+        };
+
+        // write filled template to file
+        let text = fill_template(template, &[("title", clean_text(&self.title))]);
+        match file.write_all(text.as_bytes()) {
+            Ok(_) => (),
+            Err(e) => error(format!("Failed to write to tmp file: {}", e)),
+        };
+
+        Ok(path.to_string_lossy().into_owned())
     }
 }
 
+// rendered document with the data needed for converting it
+pub struct Rendered {
+    pub html: String,
+    pub depth: u32, // deepest heading level
+    pub lists: Lists,
+}
+
 impl Document {
-    pub fn build(style: Stylesheet, content: String, matches: &ArgMatches) -> String {
+    pub fn build(style: Stylesheet, theme: &Theme, content: String, catalog: &Catalog) -> String {
         // create new document
         let new = Document {
-            header: Header::new(style, Themes::parse(&matches), &Languages::parse(&matches)),
+            lang: catalog.lang.clone(),
+            header: Header::new(style, theme.css.clone(), catalog),
             content,
         };
 
@@ -217,24 +238,11 @@ impl Document {
         }
     }
 
-    pub fn to_file(html: String) -> Result<String, IOError> {
-        // check path
-        let mut raw_path = DOCUMENT_PATH.to_owned();
-        let mut path = Path::new(&raw_path);
-
-        // when path exists already fall back to {number} - path
-        if path.exists() {
-            let mut i = 1;
-
-            while path.exists() {
-                raw_path = format!("./.document-{}.html", i);
-                path = Path::new(&raw_path);
-                i += 1;
-            }
-        }
+    pub fn to_file(html: String, artifacts: &Artifacts) -> Result<String, IOError> {
+        let path = artifacts.path(DOCUMENT_NAME);
 
         // Create a file
-        let mut file = match File::create(path) {
+        let mut file = match File::create(&path) {
             Ok(file) => file,
             Err(e) => error(format!("Failed to create tmp file: {}", e)),
         };
@@ -245,45 +253,70 @@ impl Document {
             Err(e) => error(format!("Failed to write to tmp file: {}", e)),
         };
 
-        Ok(raw_path)
+        Ok(path.to_string_lossy().into_owned())
     }
+}
 
-    // Removes footer and document artifacts
-    pub fn remove_artifacts() -> Result<(), IOError> {
-        for entry in read_dir(".")? {
-            let entry = entry?;
-            let path = entry.path();
-
-            match path.to_str() {
-                // only check if able to convert to string because there would be no way to determine without file name
-                Some(stringified) => {
-                    // check if is file and matching tmp file patterns
-                    if path.is_file() && stringified.starts_with("./.document")
-                        || stringified.starts_with("./.footer")
-                    {
-                        match remove_file(&path) {
-                            Ok(_) => {
-                                // print info if removing more than default tmp footer file
-                                if stringified != FOOTER_PATH {
-                                    info(format!("Removed old document artifact: {}", stringified));
-                                }
-                            }
-                            Err(e) => {
-                                // return in case of an error to caller
-                                return Err(e);
-                            }
-                        }
-                    }
-                }
-                // might add an option to surpress any warning later on
-                None => warning("Failed to parse a part of the current dir"),
-            };
+/* Private directory for the intermediate files (footer, header, document) of one run */
+pub struct Artifacts {
+    dir: TempDir,
+}
+
+// directory of the current run, removed when exiting early since exit() skips destructors
+static CURRENT: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+impl Artifacts {
+    pub fn new() -> Artifacts {
+        match Builder::new().prefix("mdpdf-").tempdir() {
+            Ok(dir) => {
+                *CURRENT.lock().unwrap() = Some(dir.path().to_path_buf());
+                Artifacts { dir }
+            }
+            Err(e) => error(format!("Failed to create temporary directory: {}", e)),
         }
+    }
+
+    pub fn path(&self, name: &str) -> PathBuf {
+        self.dir.path().join(name)
+    }
 
-        Ok(())
+    // keeps the directory after the run (-k) and returns its path
+    pub fn keep(self) -> PathBuf {
+        CURRENT.lock().unwrap().take();
+        self.dir.keep()
+    }
+
+    // removes the directory of the current run if there is one
+    pub fn cleanup() {
+        let current = match CURRENT.lock() {
+            Ok(mut current) => current.take(),
+            Err(_) => None,
+        };
+
+        if let Some(dir) = current {
+            if let Err(e) = remove_dir_all(&dir) {
+                warning(format!(
+                    "Failed to remove temporary directory {}: {}",
+                    dir.display(),
+                    e
+                ));
+            }
+        }
     }
 }
 
 // static values
-static DOCUMENT_PATH: &'static str = "./.document.html";
-static FOOTER_PATH: &'static str = "./.footer.html";
+static DOCUMENT_NAME: &'static str = "document.html";
+static FOOTER_NAME: &'static str = "footer.html";
+static HEADER_NAME: &str = "header.html";
+
+// replaces {{ key }} placeholders of theme package templates
+fn fill_template(template: &str, values: &[(&str, String)]) -> String {
+    let mut filled = template.to_owned();
+    for (key, value) in values {
+        filled = filled
+            .replace(&format!("{{{{ {} }}}}", key), value)
+            .replace(&format!("{{{{{}}}}}", key), value);
+    }
+    filled
+}
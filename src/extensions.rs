@@ -0,0 +1,453 @@
+use clap::ArgMatches;
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event, Tag};
+
+/* mdpdf extensions on top of the pulldown-cmark ones, selected with --extensions too:
+definition lists, abbreviations, ^superscript^, ~subscript~, ==highlight==, ++Ctrl+C++
+and emoji (:warning: shortcodes and unicode emoji in the bundled emoji font) */
+#[derive(Clone, Copy)]
+pub struct Extensions {
+    pub definition_lists: bool,
+    pub abbreviations: bool,
+    pub superscript: bool,
+    pub subscript: bool,
+    pub highlight: bool,
+    pub keyboard: bool,
+    pub emoji: bool,
+}
+
+impl Extensions {
+    pub fn parse(matches: &ArgMatches) -> Extensions {
+        // all extensions are enabled by default like the pulldown-cmark ones
+        let enabled: Option<Vec<&str>> = matches.values_of("extensions").map(Iterator::collect);
+        let enabled = |name: &str| match &enabled {
+            Some(extensions) => extensions.contains(&name),
+            None => true,
+        };
+
+        Extensions {
+            definition_lists: enabled("definition-lists"),
+            abbreviations: enabled("abbreviations"),
+            superscript: enabled("superscript"),
+            subscript: enabled("subscript"),
+            highlight: enabled("highlight"),
+            keyboard: enabled("keyboard"),
+            emoji: enabled("emoji"),
+        }
+    }
+
+    // inline delimiters with their tags, longer delimiters first
+    fn delimiters(&self) -> Vec<(&'static str, &'static str)> {
+        let mut delimiters = Vec::new();
+        if self.highlight {
+            delimiters.push(("==", "mark"));
+        }
+        if self.keyboard {
+            delimiters.push(("++", "kbd"));
+        }
+        if self.superscript {
+            delimiters.push(("^", "sup"));
+        }
+        if self.subscript {
+            delimiters.push(("~", "sub"));
+        }
+        delimiters
+    }
+
+    pub fn has_inline(&self, text: &str) -> bool {
+        self.delimiters()
+            .iter()
+            .any(|(delimiter, _)| text.contains(delimiter))
+            || (self.emoji && (has_shortcode(text) || has_emoji(text)))
+    }
+
+    // replaces shortcodes by the emoji, e.g. for heading titles in the outline
+    pub fn shortcodes(&self, text: &str) -> String {
+        if !self.emoji || !has_shortcode(text) {
+            return text.to_owned();
+        }
+
+        let mut replaced = String::new();
+        let mut rest = text;
+        while let Some(c) = rest.chars().next() {
+            match shortcode(rest) {
+                Some((found, end)) => {
+                    replaced.push_str(found);
+                    rest = &rest[end..];
+                }
+                None => {
+                    replaced.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+
+        replaced
+    }
+
+    // wraps emoji of an escaped text in spans with the emoji font, shortcodes like
+    // :white_check_mark: are replaced by the emoji
+    pub fn emoji(&self, html: &str) -> String {
+        if !self.emoji {
+            return html.to_owned();
+        }
+
+        let mut emoji = String::new();
+        let mut rest = html;
+        while let Some(c) = rest.chars().next() {
+            // tags (e.g. abbreviations) and cross-reference marks are kept as they are
+            let skipped = match c {
+                '<' => rest.find('>'),
+                '\u{1}' => rest.find('\u{2}'),
+                _ => None,
+            };
+            if let Some(end) = skipped {
+                emoji.push_str(&rest[..=end]);
+                rest = &rest[end + 1..];
+                continue;
+            }
+
+            // sequences like flags or families are joined characters
+            let found = shortcode(rest)
+                .or_else(|| emoji_length(rest).map(|length| (&rest[..length], length)));
+
+            match found {
+                Some((found, end)) => {
+                    emoji.push_str(&format!("<span class=\"emoji\">{}</span>", found));
+                    rest = &rest[end..];
+                }
+                None => {
+                    emoji.push(c);
+                    rest = &rest[c.len_utf8()..];
+                }
+            }
+        }
+
+        emoji
+    }
+
+    // html for a text with inline extensions, the remaining text is rendered by plain
+    pub fn inline(&self, text: &str, plain: &mut dyn FnMut(&str) -> String) -> String {
+        let delimiters = self.delimiters();
+        let mut html = String::new();
+        let mut start = 0; // start of the pending plain text
+        let mut i = 0;
+
+        while i < text.len() {
+            let span = delimiters.iter().find_map(|(delimiter, tag)| {
+                if !text[i..].starts_with(delimiter) {
+                    return None;
+                }
+                let content_start = i + delimiter.len();
+                let end = content_start + text[content_start..].find(delimiter)?;
+                let content = &text[content_start..end];
+                if valid_content(content, tag) {
+                    Some((tag, content, end + delimiter.len()))
+                } else {
+                    None
+                }
+            });
+
+            match span {
+                Some((tag, content, end)) => {
+                    html.push_str(&plain(&text[start..i]));
+                    if *tag == "kbd" {
+                        let keys: Vec<String> = content
+                            .split('+')
+                            .map(|key| format!("<kbd>{}</kbd>", plain(key.trim())))
+                            .collect();
+                        html.push_str(&keys.join("+"));
+                    } else {
+                        html.push_str(&format!("<{0}>{1}</{0}>", tag, plain(content)));
+                    }
+                    i = end;
+                    start = end;
+                }
+                None => {
+                    i += text[i..].chars().next().map_or(1, char::len_utf8);
+                }
+            }
+        }
+
+        html.push_str(&plain(&text[start..]));
+        html
+    }
+}
+
+// super- and subscripts can't contain spaces (e.g. H~2~O), the others can't start
+// or end with one (so e.g. 'C++ and C++' stays as it is)
+fn valid_content(content: &str, tag: &str) -> bool {
+    if content.is_empty() || content.trim() != content {
+        return false;
+    }
+
+    match tag {
+        "sup" | "sub" => !content.contains(char::is_whitespace),
+        "kbd" => content.split('+').all(|key| !key.trim().is_empty()),
+        _ => true,
+    }
+}
+
+// emoji and length of a shortcode at the start of a text, e.g. :warning:
+fn shortcode(text: &str) -> Option<(&'static str, usize)> {
+    let end = text.strip_prefix(':')?.find(':')?;
+    emojis::get_by_shortcode(&text[1..end + 1]).map(|found| (found.as_str(), end + 2))
+}
+
+fn has_shortcode(text: &str) -> bool {
+    text.match_indices(':')
+        .any(|(index, _)| shortcode(&text[index..]).is_some())
+}
+
+// characters that may be part of an emoji: Extended_Pictographic symbols, regional
+// indicators (flags), skin tones, joiners, variation selectors and keycaps
+fn is_emoji(c: char) -> bool {
+    matches!(c,
+        '\u{A9}' | '\u{AE}' | '\u{203C}' | '\u{2049}' | '\u{2122}' | '\u{2139}'
+        | '\u{2194}'..='\u{21AA}'
+        | '\u{231A}'..='\u{23FA}'
+        | '\u{24C2}'
+        | '\u{25AA}'..='\u{25FE}'
+        | '\u{2600}'..='\u{27BF}'
+        | '\u{2934}'..='\u{2935}'
+        | '\u{2B05}'..='\u{2B55}'
+        | '\u{3030}' | '\u{303D}' | '\u{3297}' | '\u{3299}'
+        | '\u{1F000}'..='\u{1FAFF}'
+        | '\u{200D}' | '\u{FE0F}' | '\u{20E3}')
+}
+
+// length of the emoji at the start of a text. Symbols that are text by default (e.g. © or ⬅)
+// are only emoji with a variation selector (⬅️), others like ⌘ aren't emoji at all
+fn emoji_length(text: &str) -> Option<usize> {
+    let mut end: usize = text
+        .chars()
+        .take_while(|c| is_emoji(*c))
+        .map(char::len_utf8)
+        .sum();
+
+    // the longest sequence, e.g. a family instead of its first person
+    while end > 0 {
+        let candidate = &text[..end];
+        if let Some(found) = emojis::get(candidate) {
+            if found.as_str() == candidate || candidate.contains('\u{FE0F}') {
+                return Some(end);
+            }
+        }
+        end = candidate
+            .char_indices()
+            .last()
+            .map_or(0, |(index, _)| index);
+    }
+
+    None
+}
+
+fn has_emoji(text: &str) -> bool {
+    text.char_indices()
+        .any(|(index, c)| is_emoji(c) && emoji_length(&text[index..]).is_some())
+}
+
+fn is_word(c: Option<char>) -> bool {
+    matches!(c, Some(c) if c.is_alphanumeric())
+}
+
+/* Abbreviations: '*[HTML]: HyperText Markup Language' lines anywhere in the document,
+all occurrences of the abbreviation are rendered as <abbr> */
+#[derive(Default)]
+pub struct Abbreviations {
+    definitions: Vec<(String, String)>, // escaped abbreviation and title, longest first
+}
+
+impl Abbreviations {
+    // removes the definitions from the markdown, lines in code blocks are kept
+    pub fn split(markdown: String) -> (String, Abbreviations) {
+        let mut definitions = Vec::new();
+        let mut kept = Vec::new();
+        let mut fence: Option<&str> = None;
+
+        for line in markdown.lines() {
+            let trimmed = line.trim_start();
+            match fence {
+                Some(marker) if trimmed.starts_with(marker) => fence = None,
+                Some(_) => (),
+                None if trimmed.starts_with("```") => fence = Some("```"),
+                None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+                None => {
+                    let definition = line
+                        .strip_prefix("*[")
+                        .and_then(|rest| rest.split_once("]:"))
+                        .filter(|(abbreviation, _)| !abbreviation.trim().is_empty());
+                    if let Some((abbreviation, title)) = definition {
+                        let mut escaped = String::new();
+                        escape_html(&mut escaped, abbreviation.trim()).unwrap();
+                        definitions.push((escaped, title.trim().to_owned()));
+                        continue;
+                    }
+                }
+            }
+            kept.push(line);
+        }
+
+        definitions.sort_by_key(|(abbreviation, _)| std::cmp::Reverse(abbreviation.len()));
+        let mut markdown = kept.join("\n");
+        markdown.push('\n');
+        (markdown, Abbreviations { definitions })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.definitions.is_empty()
+    }
+
+    // escapes a text and wraps the abbreviations (whole words only)
+    pub fn escape(&self, text: &str) -> String {
+        let mut escaped = String::new();
+        escape_html(&mut escaped, text).unwrap();
+        if self.definitions.is_empty() {
+            return escaped;
+        }
+
+        let mut html = String::new();
+        let mut rest = escaped.as_str();
+        let mut previous: Option<char> = None; // last character, abbreviations are whole words
+        'outer: while !rest.is_empty() {
+            // entities and cross-reference marks (resolved later) are kept as they are
+            let skipped = match rest.chars().next() {
+                Some('&') => rest.find(';'),
+                Some('\u{1}') => rest.find('\u{2}'),
+                _ => None,
+            };
+            if let Some(end) = skipped.map(|end| end + 1) {
+                html.push_str(&rest[..end]);
+                rest = &rest[end..];
+                previous = None;
+                continue;
+            }
+
+            if !is_word(previous) {
+                for (abbreviation, title) in self.definitions.iter() {
+                    let whole_word = rest
+                        .strip_prefix(abbreviation.as_str())
+                        .map(|after| !is_word(after.chars().next()));
+                    if whole_word == Some(true) {
+                        html.push_str("<abbr title=\"");
+                        escape_html(&mut html, title).unwrap();
+                        html.push_str(&format!("\">{}</abbr>", abbreviation));
+                        previous = abbreviation.chars().last();
+                        rest = &rest[abbreviation.len()..];
+                        continue 'outer;
+                    }
+                }
+            }
+
+            let c = rest.chars().next().unwrap();
+            html.push(c);
+            previous = Some(c);
+            rest = &rest[c.len_utf8()..];
+        }
+
+        html
+    }
+}
+
+/* Definition lists: a paragraph with a term followed by lines starting with ': '
+Term
+: Definition */
+pub fn definition_lists(events: Vec<Event>) -> Vec<Event> {
+    let mut converted = Vec::with_capacity(events.len());
+    let mut paragraph: Option<Vec<Event>> = None;
+
+    for event in events {
+        match (&mut paragraph, event) {
+            (None, Event::Start(Tag::Paragraph)) => paragraph = Some(Vec::new()),
+            (Some(_), Event::End(Tag::Paragraph)) => {
+                let inner = paragraph.take().unwrap();
+                match definition_list(inner) {
+                    Ok(list) => converted.extend(list),
+                    Err(inner) => {
+                        converted.push(Event::Start(Tag::Paragraph));
+                        converted.extend(inner);
+                        converted.push(Event::End(Tag::Paragraph));
+                    }
+                }
+            }
+            (Some(inner), event) => inner.push(event),
+            (None, event) => converted.push(event),
+        }
+    }
+
+    converted
+}
+
+// the events of a paragraph as definition list, the unchanged events if it isn't one
+fn definition_list(inner: Vec<Event>) -> Result<Vec<Event>, Vec<Event>> {
+    // split into lines at soft and hard breaks
+    let mut lines: Vec<Vec<Event>> = vec![Vec::new()];
+    for event in inner.iter() {
+        match event {
+            Event::SoftBreak | Event::HardBreak => lines.push(Vec::new()),
+            event => lines.last_mut().unwrap().push(event.clone()),
+        }
+    }
+
+    let is_definition =
+        |line: &[Event]| matches!(line.first(), Some(Event::Text(text)) if text.starts_with(": "));
+    if lines.len() < 2 || is_definition(&lines[0]) || !is_definition(&lines[1]) {
+        return Err(inner);
+    }
+
+    let mut list = vec![Event::Html(CowStr::from("<dl>"))];
+    for mut line in lines {
+        let tag = if is_definition(&line) {
+            if let Some(Event::Text(text)) = line.first_mut() {
+                *text = CowStr::from(text[2..].to_owned());
+            }
+            "dd"
+        } else {
+            "dt"
+        };
+
+        list.push(Event::Html(CowStr::from(format!("<{}>", tag))));
+        list.extend(line);
+        list.push(Event::Html(CowStr::from(format!("</{}>", tag))));
+    }
+    list.push(Event::Html(CowStr::from("</dl>\n")));
+
+    Ok(list)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Extensions;
+
+    fn extensions() -> Extensions {
+        Extensions {
+            definition_lists: false,
+            abbreviations: false,
+            superscript: false,
+            subscript: false,
+            highlight: false,
+            keyboard: false,
+            emoji: true,
+        }
+    }
+
+    #[test]
+    fn wraps_emoji() {
+        let extensions = extensions();
+        assert_eq!(
+            extensions.emoji("go 🚀 :warning: ⬅\u{FE0F}"),
+            "go <span class=\"emoji\">🚀</span> <span class=\"emoji\">⚠\u{FE0F}</span> <span class=\"emoji\">⬅\u{FE0F}</span>"
+        );
+        assert_eq!(
+            extensions.emoji("🇩🇪🚀"),
+            "<span class=\"emoji\">🇩🇪</span><span class=\"emoji\">🚀</span>"
+        );
+    }
+
+    #[test]
+    fn keeps_text_symbols() {
+        let extensions = extensions();
+        assert!(!extensions.has_inline("⌘ ⌀ ⬅ © 12:30"));
+        assert_eq!(extensions.emoji("⌘ ⌀ ⬅ ©"), "⌘ ⌀ ⬅ ©");
+    }
+}
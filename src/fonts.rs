@@ -0,0 +1,169 @@
+use crate::error;
+use crate::style::css_string;
+use clap::ArgMatches;
+use std::fs::read;
+use std::io::Error as IOError;
+use std::path::Path;
+
+/* bundled open fonts (DejaVu and Noto Emoji, see assets/fonts/LICENSE) for reproducible output */
+struct BundledFont {
+    family: &'static str,
+    regular: &'static [u8],
+    bold: Option<&'static [u8]>,
+}
+
+static BUNDLED: &[BundledFont] = &[
+    BundledFont {
+        family: "DejaVu Sans",
+        regular: include_bytes!("assets/fonts/DejaVuSans.ttf"),
+        bold: Some(include_bytes!("assets/fonts/DejaVuSans-Bold.ttf")),
+    },
+    BundledFont {
+        family: "DejaVu Sans Mono",
+        regular: include_bytes!("assets/fonts/DejaVuSansMono.ttf"),
+        bold: None,
+    },
+];
+
+// monochrome emoji font for the emoji extension, independent of the fonts of the host
+static EMOJI: &[u8] = include_bytes!("assets/fonts/NotoEmoji-Regular.ttf");
+
+// supported font files
+static FONT_EXTENSIONS: &[&str] = &["ttf", "otf", "woff", "woff2"];
+
+// font options with the selectors they apply to, their default (--bundled-fonts) and generic fallback
+static TARGETS: &[(&str, &str, &str, &str)] = &[
+    ("font-body", "body", "DejaVu Sans", "sans-serif"),
+    (
+        "font-heading",
+        "h1,h2,h3,h4,h5,h6",
+        "DejaVu Sans",
+        "sans-serif",
+    ),
+    (
+        "font-mono",
+        "pre,code,kbd,samp",
+        "DejaVu Sans Mono",
+        "monospace",
+    ),
+];
+
+// option value for the fonts of the host, the default without --bundled-fonts
+static SYSTEM: &str = "system";
+
+// @font-face rule with the font embedded as base64 data uri
+fn font_face(family: &str, weight: &str, data: &[u8], extension: &str) -> String {
+    let mime = match extension {
+        "otf" => "font/otf",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        _ => "font/ttf",
+    };
+
+    format!(
+        "@font-face{{font-family:{};font-weight:{};src:url(data:{};base64,{})}}",
+        css_string(family),
+        weight,
+        mime,
+        base64::encode(data)
+    )
+}
+
+// embedded emoji font for the spans of the emoji extension (<span class="emoji">)
+pub fn emoji() -> String {
+    format!(
+        "{}.emoji{{font-family:{};font-style:normal;font-weight:normal}}",
+        font_face("Noto Emoji", "normal", EMOJI, "ttf"),
+        css_string("Noto Emoji")
+    )
+}
+
+pub fn is_font_file(path: &Path) -> bool {
+    match path.extension() {
+        Some(ext) => FONT_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str()),
+        None => false,
+    }
+}
+
+// embeds a font file, the family is the file name (e.g. Inter-Regular.ttf -> Inter-Regular)
+pub fn embed_file(path: &Path) -> Result<(String, String), IOError> {
+    let family = path
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    let rule = font_face(&family, "normal", &read(path)?, &extension);
+    Ok((family, rule))
+}
+
+/* Fonts selected with --font-body, --font-heading and --font-mono, with --bundled-fonts
+the bundled fonts are used for the others so the output doesn't depend on the host */
+pub struct Fonts {
+    pub css: String,
+}
+
+impl Fonts {
+    pub fn parse(matches: &ArgMatches) -> Fonts {
+        let mut faces = String::new();
+        let mut rules = String::new();
+        let mut embedded: Vec<String> = Vec::new();
+
+        // the bundled fonts are about 2 MB, they are only embedded by default if requested
+        let bundled_defaults = matches.is_present("bundled-fonts");
+        for (option, selector, default, fallback) in TARGETS {
+            let default = if bundled_defaults { default } else { SYSTEM };
+            let value = matches.value_of(option).unwrap_or(default).trim();
+            if value.eq_ignore_ascii_case(SYSTEM) {
+                continue;
+            }
+
+            let path = Path::new(value);
+            let bundled = BUNDLED
+                .iter()
+                .find(|font| font.family.eq_ignore_ascii_case(value));
+
+            // value is either a bundled font, a font file or a family installed on the host
+            let family = if let Some(font) = bundled {
+                if !embedded.iter().any(|family| family == font.family) {
+                    faces.push_str(&font_face(font.family, "normal", font.regular, "ttf"));
+                    if let Some(bold) = font.bold {
+                        faces.push_str(&font_face(font.family, "bold", bold, "ttf"));
+                    }
+                    embedded.push(font.family.to_owned());
+                }
+                font.family.to_owned()
+            } else if is_font_file(path) {
+                if !path.is_file() {
+                    error(format!("Font file {} for --{} wasn't found", value, option));
+                }
+
+                match embed_file(path) {
+                    Ok((family, rule)) => {
+                        if !embedded.contains(&family) {
+                            faces.push_str(&rule);
+                            embedded.push(family.clone());
+                        }
+                        family
+                    }
+                    Err(e) => error(format!("Failed to load font file {}: {}", value, e)),
+                }
+            } else {
+                value.to_owned()
+            };
+
+            rules.push_str(&format!(
+                "{}{{font-family:{},{}}}",
+                selector,
+                css_string(&family),
+                fallback
+            ));
+        }
+
+        faces.push_str(&rules);
+        Fonts { css: faces }
+    }
+}
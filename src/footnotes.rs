@@ -0,0 +1,200 @@
+use crate::warning;
+use clap::ArgMatches;
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{CowStr, Event};
+use std::collections::HashMap;
+
+/* Footnotes: numbered in order of their first reference and rendered as endnotes
+or below the block referencing them (--footnotes) */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Placement {
+    End,
+    Block,
+}
+
+impl Placement {
+    pub fn parse(matches: &ArgMatches) -> Placement {
+        match matches.value_of("footnotes") {
+            Some("block") => Placement::Block,
+            _ => Placement::End,
+        }
+    }
+}
+
+pub struct Footnotes {
+    placement: Placement,
+    numbers: HashMap<String, usize>, // number of each referenced label
+    labels: Vec<String>,             // labels in order of their numbers
+    references: Vec<usize>,          // count of references for each number
+    definitions: HashMap<String, String>,
+    pending: Vec<usize>, // numbers referenced in the current block
+    placements: Vec<(usize, Vec<usize>)>, // event index and numbers for block placement
+}
+
+impl Footnotes {
+    pub fn new(placement: Placement) -> Footnotes {
+        Footnotes {
+            placement,
+            numbers: HashMap::new(),
+            labels: Vec::new(),
+            references: Vec::new(),
+            definitions: HashMap::new(),
+            pending: Vec::new(),
+            placements: Vec::new(),
+        }
+    }
+
+    // html for a reference, every reference gets its own id for the back-links
+    pub fn reference(&mut self, label: &str) -> String {
+        let number = match self.numbers.get(label) {
+            Some(number) => *number,
+            None => {
+                self.labels.push(label.to_owned());
+                self.references.push(0);
+                self.numbers.insert(label.to_owned(), self.labels.len());
+                self.pending.push(self.labels.len());
+                self.labels.len()
+            }
+        };
+
+        self.references[number - 1] += 1;
+        format!(
+            "<sup class=\"footnote-reference\" id=\"{}\"><a href=\"#fn-{}\">{}</a></sup>",
+            reference_id(number, self.references[number - 1]),
+            number,
+            number
+        )
+    }
+
+    pub fn define(&mut self, label: &str, html: String) {
+        if self.definitions.insert(label.to_owned(), html).is_some() {
+            warning(format!("Footnote [^{}] is defined more than once", label));
+        }
+    }
+
+    // called at the end of each top level block, footnotes placed per block follow it
+    pub fn block_end(&mut self, index: usize) {
+        if !self.pending.is_empty() {
+            self.placements
+                .push((index, self.pending.drain(..).collect()));
+        }
+    }
+
+    // rendered footnote with back-links to all of its references
+    fn note(&self, number: usize) -> String {
+        let label = &self.labels[number - 1];
+        let mut content = match self.definitions.get(label) {
+            Some(content) => content.trim_end().to_owned(),
+            None => {
+                let mut escaped = String::new();
+                escape_html(&mut escaped, label).unwrap();
+                warning(format!(
+                    "Footnote [^{}] is referenced but not defined",
+                    label
+                ));
+                format!("<p>[^{}]</p>", escaped)
+            }
+        };
+
+        let backlinks: String = (1..=self.references[number - 1])
+            .map(|i| {
+                format!(
+                    " <a class=\"footnote-backref\" href=\"#{}\">↩</a>",
+                    reference_id(number, i)
+                )
+            })
+            .collect();
+
+        // back-links belong into the last paragraph of the footnote
+        match content.strip_suffix("</p>") {
+            Some(stripped) => content = format!("{}{}</p>", stripped, backlinks),
+            None => content.push_str(&backlinks),
+        }
+
+        format!(
+            "<div class=\"footnote\" id=\"fn-{}\"><span class=\"footnote-label\">{}</span>{}</div>",
+            number, number, content
+        )
+    }
+
+    // adds the footnotes to the document events, either after the blocks referencing them
+    // or as endnote section at the end
+    pub fn finish(mut self, events: &mut Vec<Event>) {
+        for label in self.definitions.keys() {
+            if !self.numbers.contains_key(label) {
+                warning(format!("Footnote [^{}] is never referenced", label));
+            }
+        }
+
+        if self.labels.is_empty() {
+            return;
+        }
+
+        match self.placement {
+            Placement::Block => {
+                // e.g. references from other footnotes
+                self.block_end(events.len());
+
+                for (index, numbers) in self.placements.iter().rev() {
+                    let notes: String = numbers.iter().map(|number| self.note(*number)).collect();
+                    events.insert(
+                        *index,
+                        Event::Html(CowStr::from(format!(
+                            "<div class=\"footnotes footnotes-block\">{}</div>\n",
+                            notes
+                        ))),
+                    );
+                }
+            }
+            Placement::End => {
+                let notes: String = (1..=self.labels.len())
+                    .map(|number| self.note(number))
+                    .collect();
+                events.push(Event::Html(CowStr::from(format!(
+                    "<div class=\"footnotes\">{}</div>\n",
+                    notes
+                ))));
+            }
+        }
+    }
+}
+
+// consecutive definitions ([^1]: one\n[^2]: two) would be parsed as a single one with a
+// reference in it, a blank line is inserted between them. Lines in code blocks are kept
+pub fn separate_definitions(markdown: String) -> String {
+    let mut separated = String::with_capacity(markdown.len());
+    let mut fence: Option<&str> = None;
+    let mut previous_blank = true;
+
+    for line in markdown.split_inclusive('\n') {
+        let trimmed = line.trim_start();
+        match fence {
+            Some(marker) if trimmed.starts_with(marker) => fence = None,
+            Some(_) => (),
+            None if trimmed.starts_with("```") => fence = Some("```"),
+            None if trimmed.starts_with("~~~") => fence = Some("~~~"),
+            None if !previous_blank && is_definition(line) => separated.push('\n'),
+            None => (),
+        }
+        separated.push_str(line);
+        previous_blank = trimmed.is_empty();
+    }
+
+    separated
+}
+
+// start of a footnote definition, e.g. '[^note]: text'
+fn is_definition(line: &str) -> bool {
+    matches!(
+        line.strip_prefix("[^").and_then(|rest| rest.split_once("]:")),
+        Some((label, _)) if !label.is_empty() && !label.contains(']')
+    )
+}
+
+fn reference_id(number: usize, reference: usize) -> String {
+    if reference == 1 {
+        format!("fnref-{}", number)
+    } else {
+        format!("fnref-{}-{}", number, reference)
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+/* Front matter: simple 'key: value' block between '---' lines at the start of a document */
+pub struct FrontMatter {
+    entries: HashMap<String, String>,
+}
+
+// keys are single words like 'columns' or 'citation-style'
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+impl FrontMatter {
+    // splits the front matter from the markdown, documents without one get an empty front matter
+    pub fn split(markdown: String) -> (FrontMatter, String) {
+        let mut entries = HashMap::new();
+
+        let mut lines = markdown.split_inclusive('\n');
+        let mut consumed = match lines.next() {
+            Some(line) if line.trim_end() == "---" => line.len(),
+            _ => return (FrontMatter { entries }, markdown),
+        };
+
+        for line in lines {
+            consumed += line.len();
+
+            let trimmed = line.trim();
+            if (trimmed == "---" || trimmed == "...") && !entries.is_empty() {
+                let body = markdown.get(consumed..).unwrap_or("").to_owned();
+                return (FrontMatter { entries }, body);
+            } else if trimmed.is_empty() {
+                continue;
+            }
+
+            // any other line means this isn't front matter but e.g. a thematic break
+            match trimmed
+                .split_once(':')
+                .filter(|(key, _)| is_key(key.trim()))
+            {
+                Some((key, value)) => {
+                    let value = value.trim().trim_matches(|c| c == '"' || c == '\'');
+                    entries.insert(key.trim().to_lowercase(), value.to_owned());
+                }
+                None => break,
+            }
+        }
+
+        // without a closing line this wasn't front matter either
+        (
+            FrontMatter {
+                entries: HashMap::new(),
+            },
+            markdown,
+        )
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.get(key).map(|value| value.as_str())
+    }
+}
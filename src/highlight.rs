@@ -1,17 +1,26 @@
-use ammonia::{Builder, UrlRelative};
-use maplit::{hashmap, hashset};
-use pulldown_cmark::{html, CodeBlockKind, CowStr, Event, Options, Parser, Tag};
-use syntect::highlighting::ThemeSet;
+use crate::citations::{Bibliography, Citations};
+use crate::crossref::{Attributes, Caption, CrossReferences};
+use crate::error;
+use crate::extensions::{definition_lists, Abbreviations, Extensions};
+use crate::footnotes::{separate_definitions, Footnotes, Placement};
+use crate::images::{Image, ImageOptions};
+use crate::links::{split_wiki, Links, Piece};
+use crate::locale::Catalog;
+use crate::sanitize::Policy;
+use clap::ArgMatches;
+use pulldown_cmark::escape::escape_html;
+use pulldown_cmark::{
+    html, Alignment, CodeBlockKind, CowStr, Event, LinkType, Options, Parser, Tag,
+};
+use syntect::highlighting::Theme;
 use syntect::html::highlighted_html_for_string;
 use syntect::parsing::SyntaxSet;
 
 // add code highlighting
 // this function is only applied to fenced code blocks with a *language token*
 // otherwise there is no way to evaluate the syntax required for
-fn highlight(source: &String, language: &String) -> String {
+fn highlight(source: &String, language: &String, theme: &Theme) -> String {
     let ss = SyntaxSet::load_defaults_newlines();
-    let ts = ThemeSet::load_defaults();
-    let theme = &ts.themes["base16-ocean.dark"];
     let sr = ss
         .find_syntax_by_token(&language)
         .unwrap_or_else(|| ss.find_syntax_plain_text());
@@ -19,69 +28,501 @@ fn highlight(source: &String, language: &String) -> String {
     highlighted_html_for_string(&source, &ss, sr, theme)
 }
 
+// heading collected while parsing, used for e.g. the PDF outline
+pub struct Heading {
+    pub level: u32,
+    pub title: String,
+}
+
+// rendered html with additional data collected from the markdown events
+pub struct Parsed {
+    pub html: String,
+    pub headings: Vec<Heading>,
+    pub figures: Vec<Caption>,
+    pub tables: Vec<Caption>,
+}
+
+impl Parsed {
+    // deepest heading level in the document (at least 1)
+    pub fn max_level(&self) -> u32 {
+        self.headings
+            .iter()
+            .map(|heading| heading.level)
+            .max()
+            .unwrap_or(1)
+    }
+}
+
+// table rendering options (--zebra, --landscape-tables)
+pub struct TableOptions {
+    pub zebra: bool,
+    pub landscape: Option<usize>, // tables with more columns are rotated to landscape
+}
+
+impl TableOptions {
+    pub fn parse(matches: &ArgMatches) -> TableOptions {
+        let landscape = matches.value_of("landscape-tables").map(|columns| {
+            match columns.trim().parse::<usize>() {
+                Ok(columns) if columns > 0 => columns,
+                _ => error(format!(
+                    "Invalid column count '{}' for --landscape-tables: expected a positive number",
+                    columns
+                )),
+            }
+        });
+
+        TableOptions {
+            zebra: matches.is_present("zebra"),
+            landscape,
+        }
+    }
+}
+
+// options for rendering the markdown
+pub struct RenderOptions<'a> {
+    pub policy: Policy,
+    pub code_theme: &'a Theme,
+    pub tables: TableOptions,
+    pub images: ImageOptions,
+    pub footnotes: Placement,
+    pub bibliography: Option<Bibliography>,
+    pub numbering: bool, // --number-sections
+    pub links: Links,
+    pub extensions: Extensions,
+}
+
+// opening tag for a table cell with the column alignment as class
+fn table_cell(head: bool, alignment: Option<&Alignment>) -> String {
+    let tag = if head { "th" } else { "td" };
+    match alignment {
+        Some(Alignment::Left) => format!("<{} class=\"align-left\">", tag),
+        Some(Alignment::Center) => format!("<{} class=\"align-center\">", tag),
+        Some(Alignment::Right) => format!("<{} class=\"align-right\">", tag),
+        _ => format!("<{}>", tag),
+    }
+}
+
+// merges adjacent text events, the parser splits texts e.g. at brackets ([@key])
+fn merge_text<'a>(events: impl Iterator<Item = Event<'a>>) -> Vec<Event<'a>> {
+    let mut merged: Vec<Event> = Vec::new();
+    for event in events {
+        match (merged.last_mut(), event) {
+            (Some(Event::Text(last)), Event::Text(text)) => {
+                *last = CowStr::from(format!("{}{}", last, text));
+            }
+            (_, event) => merged.push(event),
+        }
+    }
+    merged
+}
+
 // parse html
-pub fn parse_html(markdown: String, options: Options, safe: bool) -> String {
+pub fn parse_html(
+    markdown: String,
+    options: Options,
+    render: &RenderOptions,
+    catalog: &Catalog,
+) -> Parsed {
     // indicator if next block needs to syntax highlighted
     let mut code_inidicator = false;
     let mut code = String::new(); // contain all code for one block in one string to only highlight once per block
     let mut language = String::new(); // container for language token in fenced code block
+    let mut indented_code = false; // indicator if text is part of an indented code block, it isn't highlighted
+    let mut headings = Vec::new(); // all headings in order of appearance
+    let mut heading: Option<Heading> = None; // heading currently being collected
+    let mut heading_start = 0; // event index of the current heading
+    let mut heading_attributes: Option<Attributes> = None; // attributes at the end of the current heading
+    let mut alignments: Vec<Alignment> = Vec::new(); // column alignments of the current table
+    let mut table_head = false; // indicator if cells are part of the table head
+    let mut cell_index = 0; // index of the current cell in its row
+    let mut landscape = false; // indicator if the current table is rotated
+    let mut image: Option<Image> = None; // image whose alt text is currently being collected
+    let mut pending_image: Option<Image> = None; // finished image, may be followed by size attributes
+    let mut references = CrossReferences::new(render.numbering);
+    let mut links = Vec::new(); // destinations of all links
+    let mut table_start: Option<usize> = None; // event index of the current table
+    let mut after_table: Option<usize> = None; // event index of the table a caption may follow
+    let mut footnotes = Footnotes::new(render.footnotes);
+    let mut citations = render.bibliography.as_ref().map(Citations::new);
+    let mut definition: Option<(String, usize)> = None; // label and first event of the current footnote definition
+    let mut depth = 0; // nesting depth of the current event, 0 is between top level blocks
 
     // Create a new vector of events since we can only consume the parser once
     let mut highlighted_html = Vec::new();
 
     // Set up options and parser
-    Parser::new_ext(&markdown, options).for_each(|event| match event {
-        Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
-            // set values to catch following text blocks
-            language = lang.clone().into_string(); // this is required to find the language for syntax highlighting later
-            code_inidicator = true;
-            highlighted_html.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
-        }
-        Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
-            highlighted_html.push(Event::Html(CowStr::from(highlight(&code, &language))));
-            highlighted_html.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
+    let (markdown, abbreviations) = if render.extensions.abbreviations {
+        Abbreviations::split(markdown)
+    } else {
+        (markdown, Abbreviations::default())
+    };
+    let markdown = if options.contains(Options::ENABLE_FOOTNOTES) {
+        separate_definitions(markdown)
+    } else {
+        markdown
+    };
+    let mut events = merge_text(Parser::new_ext(&markdown, options));
+    if render.extensions.definition_lists {
+        events = definition_lists(events);
+    }
+    let mut events = events.into_iter().peekable();
+    while let Some(event) = events.next() {
+        let block_end = match event {
+            Event::Start(_) => {
+                depth += 1;
+                false
+            }
+            Event::End(_) => {
+                depth -= 1;
+                depth == 0
+            }
+            _ => false,
+        };
 
-            // reset values
-            code_inidicator = false;
-            code = String::new();
-        }
-        Event::Text(text) => {
-            if code_inidicator {
-                code.push_str(&text)
-            } else {
-                highlighted_html.push(Event::Text(text));
+        // attributes directly following an image, e.g. ![alt](x.png){#fig:arch width=50%}
+        let event = match pending_image.take() {
+            Some(mut finished) => {
+                let mut rest = None;
+                if let Event::Text(text) = &event {
+                    if let (true, Some(end)) = (text.starts_with('{'), text.find('}')) {
+                        finished.set_attributes(&text[1..end]);
+                        rest = Some(text[end + 1..].to_owned());
+                    }
+                }
+
+                highlighted_html.push(Event::Html(CowStr::from(
+                    finished.to_html(&mut references, catalog),
+                )));
+                match rest {
+                    Some(rest) if rest.is_empty() => continue,
+                    Some(rest) => Event::Text(CowStr::from(rest)),
+                    None => event,
+                }
             }
-        }
-        Event::TaskListMarker(status) => {
-            // use boostrap 5 checkboxes instead of the ugly default ones
-            if status {
-                highlighted_html.push(Event::Html(CowStr::from(CHECKBOX_TOGGLED)));
-            } else {
-                highlighted_html.push(Event::Html(CowStr::from(CHECKBOX)));
+            None => event,
+        };
+
+        // only the paragraph directly following a table may be its caption
+        let table_before = after_table.take();
+
+        match event {
+            Event::Start(Tag::Image(_, destination, title)) => {
+                image = Some(Image {
+                    src: render.images.resolve(&destination),
+                    title: title.into_string(),
+                    alt: String::new(),
+                    style: String::new(),
+                    id: None,
+                });
             }
+            Event::End(Tag::Image(..)) => {
+                pending_image = image.take();
+            }
+            Event::Text(text) | Event::Code(text) if image.is_some() => {
+                if let Some(current) = image.as_mut() {
+                    current.alt.push_str(&text);
+                }
+            }
+            // formatting inside of the alt text is dropped
+            _ if image.is_some() => (),
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                // set values to catch following text blocks
+                language = lang.clone().into_string(); // this is required to find the language for syntax highlighting later
+                code_inidicator = true;
+                highlighted_html.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(lang))) => {
+                highlighted_html.push(Event::Html(CowStr::from(highlight(
+                    &code,
+                    &language,
+                    render.code_theme,
+                ))));
+                highlighted_html.push(Event::End(Tag::CodeBlock(CodeBlockKind::Fenced(lang))));
+
+                // reset values
+                code_inidicator = false;
+                code = String::new();
+            }
+            Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)) => {
+                indented_code = true;
+                highlighted_html.push(Event::Start(Tag::CodeBlock(CodeBlockKind::Indented)));
+            }
+            Event::End(Tag::CodeBlock(CodeBlockKind::Indented)) => {
+                indented_code = false;
+                highlighted_html.push(Event::End(Tag::CodeBlock(CodeBlockKind::Indented)));
+            }
+            Event::Start(Tag::Heading(level)) => {
+                heading = Some(Heading {
+                    level,
+                    title: String::new(),
+                });
+                heading_start = highlighted_html.len();
+                highlighted_html.push(Event::Start(Tag::Heading(level)));
+            }
+            Event::End(Tag::Heading(level)) => {
+                if let Some(finished) = heading.take() {
+                    let mut attributes = heading_attributes
+                        .take()
+                        .unwrap_or_else(|| Attributes::parse(""));
+
+                    // every heading gets an id for internal links, e.g. [see setup](#setup)
+                    let id = match attributes.id.take() {
+                        Some(id) => id,
+                        None => references.slug(&finished.title),
+                    };
+                    attributes.id = Some(id.clone());
+
+                    let number = references.section(level, &finished.title, &attributes, catalog);
+                    let mut html = format!("<h{} id=\"", level);
+                    escape_html(&mut html, &id).unwrap();
+                    html.push_str("\">");
+                    if let Some(number) = number {
+                        html.push_str(&format!(
+                            "<span class=\"section-number\">{}</span> ",
+                            number
+                        ));
+                    }
+                    highlighted_html[heading_start] = Event::Html(CowStr::from(html));
+                    headings.push(finished);
+                }
+                highlighted_html.push(Event::End(Tag::Heading(level)));
+            }
+            Event::Text(text) => {
+                if code_inidicator {
+                    code.push_str(&text)
+                } else if indented_code {
+                    // code is written as it is, without links, references or extensions
+                    highlighted_html.push(Event::Text(text));
+                } else {
+                    let mut text = text;
+                    if let Some(current) = heading.as_mut() {
+                        // attributes at the end of the heading, e.g. '# Results {#sec:results}'
+                        if matches!(events.peek(), Some(Event::End(Tag::Heading(_)))) {
+                            if let Some((stripped, parsed)) = Attributes::split(&text) {
+                                let stripped = stripped.trim_end().to_owned();
+                                heading_attributes = Some(parsed);
+                                text = CowStr::from(stripped);
+                            }
+                        }
+                        current.title.push_str(&render.extensions.shortcodes(&text));
+                    }
+
+                    for piece in split_wiki(&text) {
+                        let text = match piece {
+                            // wiki links are written as links to the generated PDF of the note
+                            Piece::Wiki(content) => {
+                                let (destination, checked, label) = render.links.wiki(content);
+                                links.push(checked);
+                                let tag = Tag::Link(
+                                    LinkType::Inline,
+                                    CowStr::from(destination),
+                                    CowStr::from(""),
+                                );
+                                highlighted_html.push(Event::Start(tag.clone()));
+                                highlighted_html.push(Event::Text(CowStr::from(label)));
+                                highlighted_html.push(Event::End(tag));
+                                continue;
+                            }
+                            Piece::Text(text) => text,
+                        };
+
+                        let text = match CrossReferences::mark(text) {
+                            Some(marked) => CowStr::from(marked),
+                            None => CowStr::from(text.to_owned()),
+                        };
+                        let cited = citations.is_some() && text.contains("[@");
+                        if !cited
+                            && abbreviations.is_empty()
+                            && !render.extensions.has_inline(&text)
+                        {
+                            highlighted_html.push(Event::Text(text));
+                            continue;
+                        }
+
+                        // abbreviations, citations, emoji and inline extensions (e.g. ==highlight==)
+                        let escape =
+                            |plain: &str| render.extensions.emoji(&abbreviations.escape(plain));
+                        let html = render.extensions.inline(&text, &mut |plain| match citations
+                            .as_mut()
+                        {
+                            Some(citations) if plain.contains("[@") => {
+                                citations.cite(plain, &escape)
+                            }
+                            _ => escape(plain),
+                        });
+                        highlighted_html.push(Event::Html(CowStr::from(html)));
+                    }
+                }
+            }
+            Event::Code(text) => {
+                if let Some(current) = heading.as_mut() {
+                    current.title.push_str(&text);
+                }
+                highlighted_html.push(Event::Code(text));
+            }
+            // tables are written here to render the alignment as class (with a repeatable thead)
+            Event::Start(Tag::Table(columns)) => {
+                landscape =
+                    matches!(render.tables.landscape, Some(threshold) if columns.len() > threshold);
+                alignments = columns;
+
+                let mut html = String::new();
+                table_start = Some(highlighted_html.len());
+                if landscape {
+                    html.push_str("<div class=\"landscape\">");
+                }
+                html.push_str(if render.tables.zebra {
+                    "<table class=\"zebra\">"
+                } else {
+                    "<table>"
+                });
+                highlighted_html.push(Event::Html(CowStr::from(html)));
+            }
+            Event::End(Tag::Table(_)) => {
+                let mut html = String::from("</tbody></table>\n");
+                if landscape {
+                    html.push_str("</div>\n");
+                }
+                highlighted_html.push(Event::Html(CowStr::from(html)));
+                after_table = table_start.take();
+            }
+            // a paragraph directly following a table starting with ': ' is its caption, it's
+            // taken from the raw text before any inline rendering (e.g. emoji or citations)
+            Event::Start(Tag::Paragraph)
+                if table_before.is_some()
+                    && matches!(events.peek(), Some(Event::Text(text)) if text.starts_with(": ")) =>
+            {
+                let mut text = String::new();
+                for event in events.by_ref() {
+                    match event {
+                        Event::End(Tag::Paragraph) => break,
+                        Event::Text(part) | Event::Code(part) => text.push_str(&part),
+                        Event::SoftBreak | Event::HardBreak => text.push(' '),
+                        _ => (),
+                    }
+                }
+                depth -= 1;
+
+                let text = text.strip_prefix(": ").unwrap_or(&text).trim();
+                let text = CrossReferences::mark(text).unwrap_or_else(|| text.to_owned());
+                let (text, attributes) = match Attributes::split(&text) {
+                    Some((text, attributes)) => (text.trim_end(), attributes),
+                    None => (text.as_str(), Attributes::parse("")),
+                };
+
+                let caption = references.table(attributes.id.as_deref(), text, catalog);
+                let mut html = String::from("<caption id=\"");
+                escape_html(&mut html, &caption.id).unwrap();
+                html.push_str("\">");
+                escape_html(&mut html, &caption.text).unwrap();
+                html.push_str("</caption>");
+
+                if let Some(table) = table_before {
+                    if let Event::Html(table_html) = &highlighted_html[table] {
+                        let table_html = format!("{}{}", table_html, html);
+                        highlighted_html[table] = Event::Html(CowStr::from(table_html));
+                    }
+                }
+            }
+            Event::Start(Tag::TableHead) => {
+                table_head = true;
+                cell_index = 0;
+                highlighted_html.push(Event::Html(CowStr::from("<thead><tr>")));
+            }
+            Event::End(Tag::TableHead) => {
+                table_head = false;
+                highlighted_html.push(Event::Html(CowStr::from("</tr></thead><tbody>\n")));
+            }
+            Event::Start(Tag::TableRow) => {
+                cell_index = 0;
+                highlighted_html.push(Event::Html(CowStr::from("<tr>")));
+            }
+            Event::End(Tag::TableRow) => {
+                highlighted_html.push(Event::Html(CowStr::from("</tr>\n")));
+            }
+            Event::Start(Tag::TableCell) => {
+                highlighted_html.push(Event::Html(CowStr::from(table_cell(
+                    table_head,
+                    alignments.get(cell_index),
+                ))));
+            }
+            Event::End(Tag::TableCell) => {
+                cell_index += 1;
+                highlighted_html.push(Event::Html(CowStr::from(if table_head {
+                    "</th>"
+                } else {
+                    "</td>"
+                })));
+            }
+            // footnote definitions are collected and placed by the footnotes later
+            Event::Start(Tag::FootnoteDefinition(label)) => {
+                definition = Some((label.into_string(), highlighted_html.len()));
+            }
+            Event::End(Tag::FootnoteDefinition(_)) => {
+                if let Some((label, start)) = definition.take() {
+                    let mut html = String::new();
+                    html::push_html(&mut html, highlighted_html.drain(start..));
+                    footnotes.define(&label, html);
+                }
+            }
+            Event::FootnoteReference(label) => {
+                highlighted_html.push(Event::Html(CowStr::from(footnotes.reference(&label))));
+            }
+            Event::Start(Tag::Link(kind, destination, title)) => {
+                let (destination, checked) = render.links.rewrite(&destination);
+                links.push(checked);
+                highlighted_html.push(Event::Start(Tag::Link(
+                    kind,
+                    CowStr::from(destination),
+                    title,
+                )));
+            }
+            Event::TaskListMarker(status) => {
+                // use boostrap 5 checkboxes instead of the ugly default ones
+                if status {
+                    highlighted_html.push(Event::Html(CowStr::from(CHECKBOX_TOGGLED)));
+                } else {
+                    highlighted_html.push(Event::Html(CowStr::from(CHECKBOX)));
+                }
+            }
+            // raw html, its images are embedded like those of markdown images
+            Event::Html(raw) => {
+                let html = render.images.inline_html(&raw);
+                highlighted_html.push(Event::Html(CowStr::from(html)));
+            }
+            event => highlighted_html.push(event),
         }
-        event => highlighted_html.push(event),
-    });
+
+        if block_end {
+            footnotes.block_end(highlighted_html.len());
+        }
+    }
+
+    // image at the end of the document
+    if let Some(finished) = pending_image.take() {
+        highlighted_html.push(Event::Html(CowStr::from(
+            finished.to_html(&mut references, catalog),
+        )));
+    }
+
+    footnotes.finish(&mut highlighted_html);
+    if let Some(citations) = citations {
+        citations.finish(&mut highlighted_html);
+    }
 
     // Write to String buffer
     let mut html_output: String = String::new();
     html::push_html(&mut html_output, highlighted_html.into_iter());
 
-    // clean html
-    if !safe {
-        html_output
-    } else {
-        Builder::default()
-        .add_generic_attributes(&["style", "type", "checked"])
-        .add_tags(&["input"])
-        .allowed_classes(
-            hashmap!["input" => hashset!["form-check-input"], "div" => hashset!["form-check", "break"], "span" => hashset!["break"]],
-        )
-        .link_rel(None)
-        .url_relative(UrlRelative::PassThrough)
-        .add_url_schemes(&["file"]) // This is risky I will probably build a custom filter for this later
-        .clean(&html_output)
-        .to_string()
+    // resolve cross-references and clean html according to the sanitization policy
+    let html = render.policy.clean(references.resolve(html_output));
+    render.links.check(&links, &html);
+
+    Parsed {
+        html,
+        headings,
+        figures: references.figures,
+        tables: references.tables,
     }
 }
 
@@ -89,3 +530,88 @@ pub fn parse_html(markdown: String, options: Options, safe: bool) -> String {
 // kept here for readability
 static CHECKBOX: &'static str = "☐ ";
 static CHECKBOX_TOGGLED: &'static str = "☑ ";
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_html, RenderOptions, TableOptions};
+    use crate::citations::Bibliography;
+    use crate::extensions::Extensions;
+    use crate::footnotes::Placement;
+    use crate::frontmatter::FrontMatter;
+    use crate::images::ImageOptions;
+    use crate::links::Links;
+    use crate::locale::Catalog;
+    use crate::remote::Remote;
+    use crate::sandbox::Sandbox;
+    use crate::sanitize::Policy;
+    use crate::{app, convert, style};
+    use tempfile::tempdir;
+
+    // renders markdown like the command line would with the given options
+    fn render(markdown: &str, args: &[&str]) -> String {
+        let dir = tempdir().unwrap();
+        let input = dir.path().join("input.md");
+        let matches =
+            app::app().get_matches_from(["mdpdf", input.to_str().unwrap()].iter().chain(args));
+
+        let catalog = Catalog::parse(&matches);
+        let theme = style::Theme::parse(&matches);
+        let sandbox = Sandbox::parse(&matches, &input);
+        let remote = Remote::parse(&matches);
+        let render = RenderOptions {
+            policy: Policy::parse(&matches),
+            code_theme: &theme.code,
+            tables: TableOptions::parse(&matches),
+            images: ImageOptions::parse(&matches, &sandbox, &remote),
+            footnotes: Placement::parse(&matches),
+            bibliography: Bibliography::parse(
+                &matches,
+                &FrontMatter::split(String::new()).0,
+                &sandbox,
+            ),
+            numbering: false,
+            links: Links::parse(&matches, &sandbox, &input),
+            extensions: Extensions::parse(&matches),
+        };
+        let options = convert::build_options(&matches);
+        parse_html(markdown.to_owned(), options, &render, &catalog).html
+    }
+
+    #[test]
+    fn keeps_literal_braces_of_headings() {
+        let html = render("## Sets {a, b}\n", &[]);
+        assert_eq!(html.trim(), "<h2 id=\"sets-a-b\">Sets {a, b}</h2>");
+    }
+
+    #[test]
+    fn splits_heading_attributes() {
+        let html = render("## Sets {#sec:sets}\n", &[]);
+        assert_eq!(html.trim(), "<h2 id=\"sec:sets\">Sets</h2>");
+    }
+
+    #[test]
+    fn separates_consecutive_footnote_definitions() {
+        let html = render("A[^1] b[^2]\n\n[^1]: one\n[^2]: two\n", &[]);
+        assert!(html.contains(
+            "<div class=\"footnote\" id=\"fn-2\"><span class=\"footnote-label\">2</span><p>two"
+        ));
+        assert!(!html.contains("[^2]"));
+    }
+
+    #[test]
+    fn places_footnotes_after_the_block() {
+        let html = render("A[^1]\n\nB\n\n[^1]: one\n", &["--footnotes", "block"]);
+        let note = html.find("footnotes-block").unwrap();
+        assert!(html.find("A<sup").unwrap() < note && note < html.find("<p>B").unwrap());
+    }
+
+    #[test]
+    fn strict_policy_keeps_titles() {
+        let html = render(
+            "[link](https://x.org \"T\") <img src=\"data:image/png;base64,AA==\" title=\"I\" width=\"10\" height=\"20\">\n",
+            &["--sanitize", "strict"],
+        );
+        assert!(html.contains("<a href=\"https://x.org\" title=\"T\""));
+        assert!(html.contains("title=\"I\" width=\"10\" height=\"20\""));
+    }
+}
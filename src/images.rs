@@ -0,0 +1,423 @@
+use crate::crossref::{Attributes, CrossReferences};
+use crate::links::decode;
+use crate::locale::Catalog;
+use crate::remote::{is_remote, Remote};
+use crate::sandbox::Sandbox;
+use crate::{error, warning};
+use clap::ArgMatches;
+use image::imageops::FilterType;
+use image::{GenericImageView, ImageFormat, ImageOutputFormat};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use std::fs::read;
+use std::ops::Range;
+use std::path::Path;
+
+/* Images: path resolution, size attributes and figure captions */
+pub struct Image {
+    pub src: String,
+    pub title: String,
+    pub alt: String,
+    pub style: String,
+    pub id: Option<String>,
+}
+
+// units allowed for width and height attributes
+static UNITS: &[&str] = &["%", "px", "pt", "mm", "cm", "in", "em", "rem"];
+
+// default jpeg quality when images are downscaled without --image-quality
+static DEFAULT_QUALITY: u8 = 85;
+
+// inline images and other urls (e.g. ftp) are kept as they are
+fn is_url(destination: &str) -> bool {
+    destination.contains("://") || destination.starts_with("data:")
+}
+
+// mime type by file extension for images that aren't recompressed
+fn mime(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .map(|ext| ext.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "svg" => "image/svg+xml",
+        "webp" => "image/webp",
+        "bmp" => "image/bmp",
+        _ => "application/octet-stream",
+    }
+}
+
+/* Options for embedding local images as data uris (--max-image-size, --image-quality) */
+pub struct ImageOptions {
+    pub sandbox: Sandbox,
+    pub remote: Remote,
+    pub max_size: Option<u32>,
+    pub quality: Option<u8>,
+}
+
+impl ImageOptions {
+    // relative image paths are resolved against the directory of the markdown file
+    pub fn parse(matches: &ArgMatches, sandbox: &Sandbox, remote: &Remote) -> ImageOptions {
+        let max_size =
+            matches
+                .value_of("max-image-size")
+                .map(|size| match size.trim().parse::<u32>() {
+                    Ok(size) if size > 0 => size,
+                    _ => error(format!(
+                        "Invalid image size '{}' for --max-image-size: expected a number of pixels",
+                        size
+                    )),
+                });
+        let quality =
+            matches
+                .value_of("image-quality")
+                .map(|quality| match quality.trim().parse::<u8>() {
+                    Ok(quality) if (1..=100).contains(&quality) => quality,
+                    _ => error(format!(
+                        "Invalid quality '{}' for --image-quality: expected a number from 1 to 100",
+                        quality
+                    )),
+                });
+
+        ImageOptions {
+            sandbox: sandbox.clone(),
+            remote: remote.clone(),
+            max_size,
+            quality,
+        }
+    }
+
+    // resolves a local image relative to the markdown file and embeds it as data uri
+    // so wkhtmltopdf doesn't need local file access. Missing images are reported here,
+    // before wkhtmltopdf silently skips them. Remote images are fetched by --remote
+    pub fn resolve(&self, destination: &str) -> String {
+        if is_remote(destination) {
+            return match self.remote.fetch(destination) {
+                Ok((mime, data)) => self.embed(&mime, data),
+                Err(e) => {
+                    warning(format!("Image {} wasn't embedded: {}", destination, e));
+                    String::new()
+                }
+            };
+        } else if destination.is_empty() || is_url(destination) {
+            return destination.to_owned();
+        }
+
+        // destinations may be percent-encoded, e.g. my%20image.png
+        let path = self.sandbox.base().join(decode(destination));
+        if path.exists() && !self.sandbox.allows(&path) {
+            warning(format!(
+                "Image {} is outside of the allowed directories and won't be embedded (see --allow-path)",
+                destination
+            ));
+            return String::new();
+        }
+
+        let data = match read(&path) {
+            Ok(data) => data,
+            Err(e) => {
+                warning(format!(
+                    "Image {} couldn't be loaded from {}: {}",
+                    destination,
+                    path.display(),
+                    e
+                ));
+                return destination.to_owned();
+            }
+        };
+
+        self.embed(mime(&path), data)
+    }
+
+    // embeds the images of raw html (<img src="pic.png">) like markdown images, wkhtmltopdf
+    // can't load them from local files either
+    pub fn inline_html(&self, html: &str) -> String {
+        let lowercase = html.to_ascii_lowercase();
+        let mut inlined = String::new();
+        let mut position = 0;
+
+        while let Some(start) = lowercase[position..].find("<img").map(|i| position + i) {
+            let end = match lowercase[start..].find('>') {
+                Some(end) => start + end,
+                None => break,
+            };
+            let (attribute, value) = match src_attribute(&lowercase[start..end]) {
+                Some((attribute, value)) => (
+                    start + attribute.start..start + attribute.end,
+                    start + value.start..start + value.end,
+                ),
+                None => {
+                    inlined.push_str(&html[position..end]);
+                    position = end;
+                    continue;
+                }
+            };
+
+            inlined.push_str(&html[position..attribute.start]);
+            inlined.push('"');
+            escape_html(&mut inlined, &self.resolve(&html[value])).unwrap();
+            inlined.push('"');
+            position = attribute.end;
+        }
+
+        inlined.push_str(&html[position..]);
+        inlined
+    }
+
+    // data uri for the (recompressed) image
+    fn embed(&self, mime: &str, data: Vec<u8>) -> String {
+        match self.recompress(&data) {
+            Some((mime, compressed)) => {
+                format!("data:{};base64,{}", mime, base64::encode(compressed))
+            }
+            None => format!("data:{};base64,{}", mime, base64::encode(data)),
+        }
+    }
+
+    // downscales and recompresses png, jpeg and gif images if requested
+    // the original is kept when it's smaller or can't be decoded (e.g. svg)
+    fn recompress(&self, data: &[u8]) -> Option<(&'static str, Vec<u8>)> {
+        if self.max_size.is_none() && self.quality.is_none() {
+            return None;
+        }
+
+        let format = image::guess_format(data).ok()?;
+        if ![ImageFormat::Png, ImageFormat::Jpeg, ImageFormat::Gif].contains(&format) {
+            return None;
+        }
+
+        let mut decoded = image::load_from_memory_with_format(data, format).ok()?;
+        let resized = match self.max_size {
+            Some(max) if decoded.width() > max || decoded.height() > max => {
+                decoded = decoded.resize(max, max, FilterType::Lanczos3);
+                true
+            }
+            _ => false,
+        };
+
+        // jpeg for images without transparency when a quality is given, png otherwise
+        let opaque = !decoded.color().has_alpha();
+        let (mime, output) = if format == ImageFormat::Jpeg || (opaque && self.quality.is_some()) {
+            let quality = self.quality.unwrap_or(DEFAULT_QUALITY);
+            ("image/jpeg", ImageOutputFormat::Jpeg(quality))
+        } else {
+            ("image/png", ImageOutputFormat::Png)
+        };
+
+        let mut compressed = Vec::new();
+        decoded.write_to(&mut compressed, output).ok()?;
+
+        if resized || compressed.len() < data.len() {
+            Some((mime, compressed))
+        } else {
+            None
+        }
+    }
+}
+
+// positions of the src attribute value of an img tag, including the quotes, and of the
+// value itself
+fn src_attribute(tag: &str) -> Option<(Range<usize>, Range<usize>)> {
+    let bytes = tag.as_bytes();
+    let name = tag
+        .match_indices("src")
+        .map(|(index, _)| index)
+        .find(|&index| index > 0 && bytes[index - 1].is_ascii_whitespace())?;
+
+    let rest = tag[name + 3..].trim_start().strip_prefix('=')?.trim_start();
+    let start = tag.len() - rest.len();
+    match rest.chars().next()? {
+        quote @ '"' | quote @ '\'' => {
+            let end = start + 1 + rest[1..].find(quote)?;
+            Some((start..end + 1, start + 1..end))
+        }
+        _ => {
+            let end = start + rest.find(char::is_whitespace).unwrap_or(rest.len());
+            Some((start..end, start..end))
+        }
+    }
+}
+
+// parses size attributes like 'width=50% height=4cm' into an inline style
+fn size_style(attributes: &str) -> String {
+    let mut style = String::new();
+
+    for attribute in attributes.split_whitespace() {
+        let (key, value) = match attribute.split_once('=') {
+            Some((key, value)) => (key.trim(), value.trim().trim_matches('"')),
+            None => {
+                warning(format!("Ignoring image attribute '{}'", attribute));
+                continue;
+            }
+        };
+
+        let number = value.trim_end_matches(|c: char| c.is_ascii_alphabetic() || c == '%');
+        let unit = &value[number.len()..];
+        let valid = number.parse::<f64>().is_ok() && (unit.is_empty() || UNITS.contains(&unit));
+
+        match key {
+            "width" | "height" if valid => {
+                // plain numbers are pixels like the html attributes
+                let unit = if unit.is_empty() { "px" } else { unit };
+                style.push_str(&format!("{}:{}{} !important;", key, number, unit));
+            }
+            "width" | "height" => warning(format!(
+                "Invalid image {} '{}': expected a number with one of the units {}",
+                key,
+                value,
+                UNITS.join(", ")
+            )),
+            _ => warning(format!("Ignoring unknown image attribute '{}'", key)),
+        }
+    }
+
+    style
+}
+
+impl Image {
+    // attributes following the image, e.g. {#fig:arch width=50%}
+    pub fn set_attributes(&mut self, attributes: &str) {
+        let attributes = Attributes::parse(attributes);
+        self.id = attributes.id;
+        self.style = size_style(&attributes.rest.join(" "));
+    }
+
+    // renders the image, images with a title or label become a figure with a numbered caption
+    pub fn to_html(&self, references: &mut CrossReferences, catalog: &Catalog) -> String {
+        let mut html = String::from("<img src=\"");
+        escape_href(&mut html, &self.src).unwrap();
+        html.push_str("\" alt=\"");
+        escape_html(&mut html, &self.alt).unwrap();
+        html.push('"');
+        if !self.style.is_empty() {
+            html.push_str(&format!(" style=\"{}\"", self.style));
+        }
+        html.push_str(" />");
+
+        if self.title.is_empty() && self.id.is_none() {
+            return html;
+        }
+
+        // labeled figures without title use the alt text as caption
+        let title = if self.title.is_empty() {
+            &self.alt
+        } else {
+            &self.title
+        };
+        let caption = references.figure(self.id.as_deref(), title, catalog);
+
+        // spans since figures are part of a paragraph
+        let mut figure = String::from("<span class=\"figure\" id=\"");
+        escape_html(&mut figure, &caption.id).unwrap();
+        figure.push_str(&format!("\">{}<span class=\"figcaption\">", html));
+        escape_html(&mut figure, &caption.text).unwrap();
+        figure.push_str("</span></span>");
+        figure
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ImageOptions;
+    use crate::remote::{Mode, Remote};
+    use crate::sandbox::Sandbox;
+    use std::fs::{create_dir, write};
+    use std::path::PathBuf;
+    use tempfile::{tempdir, TempDir};
+
+    // input directory with an image and an image next to it
+    fn setup() -> (TempDir, PathBuf) {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("input");
+        create_dir(&root).unwrap();
+        write(root.join("inside.png"), b"inside").unwrap();
+        write(dir.path().join("outside.png"), b"outside").unwrap();
+        (dir, root)
+    }
+
+    fn options(roots: Vec<PathBuf>) -> ImageOptions {
+        ImageOptions {
+            sandbox: Sandbox {
+                roots,
+                javascript: false,
+                network: false,
+            },
+            remote: Remote::new(Mode::Deny, None),
+            max_size: None,
+            quality: None,
+        }
+    }
+
+    #[test]
+    fn embeds_images_inside_the_root() {
+        let (_dir, root) = setup();
+        let resolved = options(vec![root]).resolve("inside.png");
+        assert_eq!(
+            resolved,
+            format!("data:image/png;base64,{}", base64::encode("inside"))
+        );
+    }
+
+    #[test]
+    fn skips_images_outside_the_root() {
+        let (dir, root) = setup();
+        let options = options(vec![root]);
+        let absolute = dir.path().canonicalize().unwrap().join("outside.png");
+
+        assert_eq!(options.resolve("../outside.png"), "");
+        assert_eq!(options.resolve(&absolute.to_string_lossy()), "");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinks_out_of_the_root() {
+        let (dir, root) = setup();
+        std::os::unix::fs::symlink(dir.path().join("outside.png"), root.join("link.png")).unwrap();
+        assert_eq!(options(vec![root]).resolve("link.png"), "");
+    }
+
+    #[test]
+    fn decodes_percent_encoded_paths() {
+        let (_dir, root) = setup();
+        write(root.join("in side.png"), b"inside").unwrap();
+        let resolved = options(vec![root]).resolve("in%20side.png");
+        assert_eq!(
+            resolved,
+            format!("data:image/png;base64,{}", base64::encode("inside"))
+        );
+    }
+
+    #[test]
+    fn embeds_images_of_raw_html() {
+        let (_dir, root) = setup();
+        let data = format!("data:image/png;base64,{}", base64::encode("inside"));
+        let options = options(vec![root]);
+
+        assert_eq!(
+            options.inline_html("<p><IMG alt=\"a\" SRC='inside.png'></p>"),
+            format!("<p><IMG alt=\"a\" SRC=\"{}\"></p>", data)
+        );
+        assert_eq!(
+            options.inline_html("<img src=inside.png width=10 />"),
+            format!("<img src=\"{}\" width=10 />", data)
+        );
+        assert_eq!(
+            options.inline_html("<img alt=\"src\">"),
+            "<img alt=\"src\">"
+        );
+    }
+
+    #[test]
+    fn embeds_images_of_allowed_paths() {
+        let (dir, root) = setup();
+        let allowed = dir.path().canonicalize().unwrap();
+        let resolved = options(vec![root, allowed]).resolve("../outside.png");
+        assert_eq!(
+            resolved,
+            format!("data:image/png;base64,{}", base64::encode("outside"))
+        );
+    }
+}
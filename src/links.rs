@@ -0,0 +1,327 @@
+use crate::crossref::slugify;
+use crate::sandbox::Sandbox;
+use crate::{error, warning};
+use clap::ArgMatches;
+use std::collections::{HashMap, HashSet};
+use std::fs::read_dir;
+use std::path::{Path, PathBuf};
+
+/* Links between documents and their validation (--check-links). Links to markdown files
+point to the generated PDF and wiki links ([[Other Note]]) are resolved to markdown files.
+Internal links have to point to an existing anchor and relative links to an existing file */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Off,
+    Warn,
+    Error,
+}
+
+pub struct Links {
+    pub mode: Mode,
+    base: PathBuf,                   // directory relative links are resolved against
+    input: PathBuf,                  // links to the converted document itself become internal links
+    notes: HashMap<String, PathBuf>, // markdown files wiki links may point to
+}
+
+// part of a text, either plain or a wiki link
+pub enum Piece<'a> {
+    Text(&'a str),
+    Wiki(&'a str), // content between the brackets, e.g. 'Other Note#Setup|label'
+}
+
+// splits the wiki links ([[Other Note]]) from a text
+pub fn split_wiki(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find("[[") {
+        let end = match rest[start + 2..].find("]]") {
+            Some(end) => start + 2 + end,
+            None => break,
+        };
+        let content = &rest[start + 2..end];
+        if content.trim().is_empty() || content.contains(&['[', ']'][..]) {
+            pieces.push(Piece::Text(&rest[..start + 2]));
+            rest = &rest[start + 2..];
+            continue;
+        }
+
+        if start > 0 {
+            pieces.push(Piece::Text(&rest[..start]));
+        }
+        pieces.push(Piece::Wiki(content));
+        rest = &rest[end + 2..];
+    }
+
+    if !rest.is_empty() {
+        pieces.push(Piece::Text(rest));
+    }
+    pieces
+}
+
+fn is_markdown(path: &Path) -> bool {
+    match path.extension() {
+        Some(extension) => {
+            let extension = extension.to_string_lossy().to_lowercase();
+            extension == "md" || extension == "markdown"
+        }
+        None => false,
+    }
+}
+
+// markdown files of a directory tree by their lowercase name, hidden directories (e.g. .git)
+// and symlinked directories are skipped. Files closer to the root take precedence
+fn index_markdown(dir: &Path, index: &mut HashMap<String, PathBuf>) {
+    let mut entries: Vec<_> = match read_dir(dir) {
+        Ok(entries) => entries.flatten().collect(),
+        Err(_) => return,
+    };
+    entries.sort_by_key(|entry| entry.file_name());
+
+    let mut subdirs = Vec::new();
+    for entry in entries {
+        let path = entry.path();
+        if matches!(entry.file_type(), Ok(file_type) if file_type.is_dir()) {
+            if !entry.file_name().to_string_lossy().starts_with('.') {
+                subdirs.push(path);
+            }
+        } else if is_markdown(&path) {
+            if let Some(stem) = path.file_stem() {
+                let name = stem.to_string_lossy().to_lowercase();
+                index.entry(name).or_insert(path);
+            }
+        }
+    }
+
+    for subdir in subdirs {
+        index_markdown(&subdir, index);
+    }
+}
+
+// links with a scheme (e.g. https: or mailto:) aren't checked
+fn has_scheme(destination: &str) -> bool {
+    match destination.split_once(':') {
+        // single letters are drives on windows (C:\)
+        Some((scheme, _)) => {
+            scheme.len() > 1
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+        }
+        None => false,
+    }
+}
+
+// ids and names of all elements of the html, e.g. <h2 id="setup">
+fn anchors(html: &str) -> HashSet<&str> {
+    let mut anchors = HashSet::new();
+
+    for attribute in &[" id=\"", " name=\""] {
+        for (index, _) in html.match_indices(attribute) {
+            let value = &html[index + attribute.len()..];
+            if let Some(end) = value.find('"') {
+                anchors.insert(&value[..end]);
+            }
+        }
+    }
+
+    anchors
+}
+
+// decodes percent-encoded characters, e.g. my%20file.md
+pub fn decode(destination: &str) -> String {
+    let bytes = destination.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let hex = destination
+            .get(i + 1..i + 3)
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                i += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+impl Links {
+    pub fn parse(matches: &ArgMatches, sandbox: &Sandbox, input: &Path) -> Links {
+        let mode = match matches.value_of("check-links") {
+            Some("off") => Mode::Off,
+            Some("error") => Mode::Error,
+            _ => Mode::Warn,
+        };
+
+        let base = sandbox.base().to_path_buf();
+        let mut notes = HashMap::new();
+        index_markdown(&base, &mut notes);
+
+        Links {
+            mode,
+            base,
+            input: input.canonicalize().unwrap_or_else(|_| input.to_path_buf()),
+            notes,
+        }
+    }
+
+    // points links to markdown files (other.md#setup) to the generated PDF (other.pdf#setup)
+    // or to the anchor if they link to this document. Returns the new destination
+    // and the one to check
+    pub fn rewrite(&self, destination: &str) -> (String, String) {
+        if has_scheme(destination) || destination.starts_with('#') {
+            return (destination.to_owned(), destination.to_owned());
+        }
+
+        let (path, fragment) = match destination.find('#') {
+            Some(index) => destination.split_at(index),
+            None => (destination, ""),
+        };
+        let source = Path::new(path);
+        if path.contains('?') || !is_markdown(source) {
+            return (destination.to_owned(), destination.to_owned());
+        }
+
+        match self.base.join(decode(path)).canonicalize() {
+            Ok(target) if target == self.input => {
+                let anchor = if fragment.is_empty() { "#" } else { fragment };
+                (anchor.to_owned(), anchor.to_owned())
+            }
+            _ => (
+                format!(
+                    "{}{}",
+                    source.with_extension("pdf").to_string_lossy(),
+                    fragment
+                ),
+                destination.to_owned(),
+            ),
+        }
+    }
+
+    // destination and label of a wiki link, e.g. [[Other Note#Setup|see setup]]
+    // the note is searched in the directory tree of the document
+    pub fn wiki(&self, content: &str) -> (String, String, String) {
+        let (target, label) = match content.split_once('|') {
+            Some((target, label)) => (target.trim(), label.trim()),
+            None => (content.trim(), content.trim()),
+        };
+        let (note, heading) = match target.split_once('#') {
+            Some((note, heading)) => (note.trim(), Some(heading)),
+            None => (target, None),
+        };
+        let fragment = match heading {
+            Some(heading) => format!("#{}", slugify(heading)),
+            None => String::new(),
+        };
+
+        // [[#Setup]] links to a heading of this document
+        if note.is_empty() {
+            let label = label.trim_start_matches('#').trim();
+            return (fragment.clone(), fragment, label.to_owned());
+        }
+
+        let path = match self.notes.get(&note.to_lowercase()) {
+            Some(path) => path.strip_prefix(&self.base).unwrap_or(path).to_path_buf(),
+            None => PathBuf::from(format!("{}.md", note)),
+        };
+        let (destination, checked) =
+            self.rewrite(&format!("{}{}", path.to_string_lossy(), fragment));
+        (destination, checked, label.to_owned())
+    }
+
+    // checks the link destinations of the document against the anchors of the rendered html
+    pub fn check(&self, destinations: &[String], html: &str) {
+        if self.mode == Mode::Off {
+            return;
+        }
+
+        let anchors = anchors(html);
+        let mut problems: Vec<String> = Vec::new();
+        let mut report = |problem: String| {
+            if !problems.contains(&problem) {
+                problems.push(problem);
+            }
+        };
+
+        for destination in destinations {
+            if destination.is_empty() || has_scheme(destination) {
+                continue;
+            }
+
+            if let Some(anchor) = destination.strip_prefix('#') {
+                if !anchor.is_empty()
+                    && !anchors.contains(anchor)
+                    && !anchors.contains(decode(anchor).as_str())
+                {
+                    report(format!("Link to a non-existent anchor {}", destination));
+                }
+                continue;
+            }
+
+            // query and fragment aren't part of the path
+            let path = destination
+                .split(&['#', '?'][..])
+                .next()
+                .unwrap_or_default();
+            if !path.is_empty() && !self.base.join(decode(path)).exists() {
+                report(format!("Link to a missing file {}", destination));
+            }
+        }
+
+        match self.mode {
+            Mode::Error if !problems.is_empty() => error(format!(
+                "Invalid links (see --check-links):\n  {}",
+                problems.join("\n  ")
+            )),
+            _ => {
+                for problem in problems {
+                    warning(problem);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::index_markdown;
+    use std::collections::HashMap;
+    use std::fs::{create_dir_all, write};
+    use tempfile::tempdir;
+
+    #[test]
+    fn indexes_notes_closest_to_the_root() {
+        let dir = tempdir().unwrap();
+        create_dir_all(dir.path().join("a/b")).unwrap();
+        create_dir_all(dir.path().join(".git")).unwrap();
+        write(dir.path().join("a/b/Note.md"), "").unwrap();
+        write(dir.path().join("a/note.md"), "").unwrap();
+        write(dir.path().join(".git/hidden.md"), "").unwrap();
+
+        let mut index = HashMap::new();
+        index_markdown(dir.path(), &mut index);
+        assert_eq!(index.get("note"), Some(&dir.path().join("a/note.md")));
+        assert_eq!(index.get("hidden"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn skips_symlinked_directories() {
+        let dir = tempdir().unwrap();
+        create_dir_all(dir.path().join("notes")).unwrap();
+        write(dir.path().join("notes/note.md"), "").unwrap();
+        std::os::unix::fs::symlink(dir.path(), dir.path().join("notes/loop")).unwrap();
+
+        let mut index = HashMap::new();
+        index_markdown(dir.path(), &mut index);
+        assert_eq!(index.len(), 1);
+    }
+}
@@ -0,0 +1,176 @@
+use crate::crossref::Caption;
+use crate::print::{destination, impose, PrintOptions};
+use crate::{error, warning};
+use clap::ArgMatches;
+use lopdf::{Document, Error as PDFError, Object, ObjectId};
+use pulldown_cmark::escape::{escape_href, escape_html};
+use std::collections::HashMap;
+use std::io::Read;
+use wkhtmltopdf::PdfBuilder;
+
+/* Lists of figures and tables (--lof, --lot) at the beginning of the document. The page
+numbers are taken from a first rendering in which the entries are the only internal links */
+pub struct Lists {
+    figures: Vec<Caption>,
+    tables: Vec<Caption>,
+}
+
+impl Lists {
+    pub fn parse(matches: &ArgMatches, figures: Vec<Caption>, tables: Vec<Caption>) -> Lists {
+        let list = |option: &str, captions: Vec<Caption>, kind: &str| {
+            if !matches.is_present(option) {
+                return Vec::new();
+            } else if captions.is_empty() {
+                warning(format!(
+                    "--{} is ignored, the document has no {}",
+                    option, kind
+                ));
+            }
+            captions
+        };
+
+        Lists {
+            figures: list("lof", figures, "figures with a caption"),
+            tables: list("lot", tables, "tables with a caption"),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.figures.len() + self.tables.len()
+    }
+
+    // the lists with page numbers of all entries in order, placeholders without them
+    pub fn to_html(&self, pages: Option<&[u32]>) -> String {
+        if self.len() == 0 {
+            return String::new();
+        }
+
+        let mut html = String::from("<div class=\"lists\">");
+        let mut index = 0;
+        for (class, captions) in [
+            ("list-of-figures", &self.figures),
+            ("list-of-tables", &self.tables),
+        ]
+        .iter()
+        {
+            if captions.is_empty() {
+                continue;
+            }
+
+            html.push_str(&format!("<div class=\"{}\">", class));
+            for caption in captions.iter() {
+                let page = match pages {
+                    Some(pages) => pages.get(index).map(u32::to_string).unwrap_or_default(),
+                    None => String::from("000"),
+                };
+                index += 1;
+
+                html.push_str("<div class=\"list-entry\"><a href=\"#");
+                escape_href(&mut html, &caption.id).unwrap();
+                html.push_str("\">");
+                // unresolved cross-reference marks of table captions are dropped
+                escape_html(&mut html, &caption.text.replace(char::is_control, "")).unwrap();
+                html.push_str(&format!(
+                    "</a><span class=\"list-page\">{}</span></div>",
+                    page
+                ));
+            }
+            html.push_str("</div>");
+        }
+        html.push_str("</div>\n");
+
+        html
+    }
+
+    // renders the document once to find the pages of all entries and fills them in
+    // in print mode the pages are counted after blank pages were inserted
+    pub fn paginate(
+        &self,
+        builder: &mut PdfBuilder,
+        html: String,
+        print: Option<&PrintOptions>,
+    ) -> String {
+        if self.len() == 0 {
+            return html;
+        }
+
+        // wkhtmltopdf names the destinations of internal links itself (__WKANCHOR_...), so
+        // the links can't be matched with the ids of the captions. The other internal links
+        // are disabled for the first rendering instead, this doesn't change the layout
+        let lists = self.to_html(None);
+        let first = match html.find(&lists) {
+            Some(start) => format!(
+                "{}{}{}",
+                disable_links(&html[..start]),
+                lists,
+                disable_links(&html[start + lists.len()..])
+            ),
+            None => html.clone(),
+        };
+
+        let mut pdf = Vec::new();
+        match builder.build_from_html(&first) {
+            Ok(mut out) => {
+                if let Err(e) = out.read_to_end(&mut pdf) {
+                    error(format!("Failed to read generated PDF: {}", e));
+                }
+            }
+            Err(e) => error(format!("Failed to generate PDF: {}", e)),
+        }
+
+        if let Some(options) = print {
+            pdf = match impose(&pdf, options) {
+                Ok(imposed) => imposed,
+                Err(e) => error(format!("Failed to apply print mode: {}", e)),
+            };
+        }
+
+        let pages = match link_pages(&pdf, self.len()) {
+            Ok(pages) if pages.len() == self.len() => pages,
+            Ok(_) | Err(_) => {
+                warning("Couldn't determine the pages of all figures and tables for --lof/--lot");
+                Vec::new()
+            }
+        };
+
+        html.replacen(&lists, &self.to_html(Some(&pages)), 1)
+    }
+}
+
+// internal links (href="#...") become plain elements
+fn disable_links(html: &str) -> String {
+    html.replace(" href=\"#", " data-href=\"#")
+}
+
+// pages the first count internal links of the document point to, in the first rendering
+// these are the entries of the lists
+fn link_pages(pdf: &[u8], count: usize) -> Result<Vec<u32>, PDFError> {
+    let document = Document::load_mem(pdf)?;
+    let pages = document.get_pages();
+    let numbers: HashMap<ObjectId, u32> = pages.iter().map(|(number, id)| (*id, *number)).collect();
+
+    let mut found = Vec::new();
+    for page in pages.values() {
+        let annotations = match document.get_dictionary(*page)?.get(b"Annots") {
+            Ok(annotations) => document.dereference(annotations)?.1.as_array()?,
+            Err(_) => continue,
+        };
+
+        for annotation in annotations {
+            let annotation = document.dereference(annotation)?.1.as_dict()?;
+            if annotation.get(b"Subtype").and_then(Object::as_name).ok() != Some(b"Link") {
+                continue;
+            }
+
+            // external links don't have a destination
+            if let Some(target) = destination(&document, annotation) {
+                found.push(numbers.get(&target).copied().unwrap_or_default());
+                if found.len() == count {
+                    return Ok(found);
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
@@ -0,0 +1,142 @@
+use crate::{error, warning};
+use chrono::Locale;
+use clap::ArgMatches;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fs::read_to_string;
+use std::io::Error as IOError;
+use std::path::Path;
+
+/* built-in translation catalogs - the english one is used as fallback for missing keys */
+static BUILTIN: &[(&str, &str)] = &[
+    ("en", include_str!("locales/en.lang")),
+    ("de", include_str!("locales/de.lang")),
+    ("fr", include_str!("locales/fr.lang")),
+    ("es", include_str!("locales/es.lang")),
+    ("nl", include_str!("locales/nl.lang")),
+];
+
+// Translation catalog for all static texts of a document
+pub struct Catalog {
+    pub lang: String,
+    entries: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+// parses 'key = value' lines, empty lines and lines starting with '#' are ignored
+pub fn parse_entries(source: &str) -> Result<HashMap<String, String>, String> {
+    let mut entries = HashMap::new();
+
+    for (i, line) in source.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        match line.split_once('=') {
+            Some((key, value)) => {
+                entries.insert(key.trim().to_owned(), value.trim().to_owned());
+            }
+            None => return Err(format!("line {} is missing a '=' separator", i + 1)),
+        }
+    }
+
+    Ok(entries)
+}
+
+// checks the rough structure of a BCP-47 tag (e.g. 'de', 'de-AT' or 'zh-Hant-TW')
+fn is_valid_tag(tag: &str) -> bool {
+    let mut subtags = tag.split('-');
+
+    let primary = subtags.next().unwrap_or("");
+    (2..=3).contains(&primary.len())
+        && primary.chars().all(|c| c.is_ascii_alphabetic())
+        && subtags.all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.chars().all(|c| c.is_ascii_alphanumeric())
+        })
+}
+
+impl Catalog {
+    pub fn parse(matches: &ArgMatches) -> Catalog {
+        let tag = matches.value_of("lang").unwrap_or("en").trim();
+        if !is_valid_tag(tag) {
+            error(format!(
+                "Invalid language '{}': expected a BCP-47 tag like 'en' or 'de-AT'",
+                tag
+            ));
+        }
+
+        let mut catalog = Catalog::builtin(tag);
+
+        // user supplied catalogs are applied in order on top of the built-in one
+        if let Some(paths) = matches.values_of("translations") {
+            for raw_path in paths {
+                match catalog.extend(Path::new(raw_path)) {
+                    Ok(_) => (),
+                    Err(e) => error(format!("Failed to load translations {}: {}", raw_path, e)),
+                }
+            }
+        } else if catalog.entries.is_empty() {
+            warning(format!(
+                "No translations for '{}' available. Falling back to english",
+                tag
+            ));
+        }
+
+        catalog
+    }
+
+    // built-in catalog for the primary language subtag, empty if there is none
+    fn builtin(tag: &str) -> Catalog {
+        let primary = tag.split('-').next().unwrap_or("").to_lowercase();
+        let find = |language: &str| {
+            BUILTIN
+                .iter()
+                .find(|(name, _)| *name == language)
+                .map(|(_, source)| parse_entries(source).unwrap())
+                .unwrap_or_default()
+        };
+
+        Catalog {
+            lang: tag.to_owned(),
+            entries: find(&primary),
+            fallback: find("en"),
+        }
+    }
+
+    // merges a user supplied catalog file into this one
+    pub fn extend(&mut self, path: &Path) -> Result<(), IOError> {
+        let source = read_to_string(path)?;
+        match parse_entries(&source) {
+            Ok(entries) => {
+                self.entries.extend(entries);
+                Ok(())
+            }
+            Err(e) => Err(IOError::new(std::io::ErrorKind::InvalidData, e)),
+        }
+    }
+
+    // translated text for key, falls back to english
+    pub fn get(&self, key: &str) -> &str {
+        self.entries
+            .get(key)
+            .or_else(|| self.fallback.get(key))
+            .map(|value| value.as_str())
+            .unwrap_or("")
+    }
+
+    // chrono locale for date formatting
+    // the region of the tag (de-AT -> de_AT) is preferred over the one from the catalog
+    pub fn locale(&self) -> Locale {
+        let mut subtags = self.lang.split('-');
+        let language = subtags.next().unwrap_or("").to_lowercase();
+        let region = subtags
+            .find(|subtag| subtag.len() == 2)
+            .map(|r| r.to_uppercase());
+
+        region
+            .and_then(|region| Locale::try_from(format!("{}_{}", language, region).as_str()).ok())
+            .or_else(|| Locale::try_from(self.get("locale")).ok())
+            .unwrap_or(Locale::en_GB)
+    }
+}
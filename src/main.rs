@@ -1,8 +1,23 @@
 // includes
 mod app;
+mod citations;
 mod convert;
+mod crossref;
 mod document;
+mod extensions;
+mod fonts;
+mod footnotes;
+mod frontmatter;
 mod highlight;
+mod images;
+mod links;
+mod lists;
+mod locale;
+mod outline;
+mod print;
+mod remote;
+mod sandbox;
+mod sanitize;
 mod style;
 
 // imports
@@ -16,12 +31,14 @@ use std::path::Path;
 use std::process::exit;
 
 fn error<S: Display>(e: S) -> ! {
+    document::Artifacts::cleanup();
     println!("{}: {}", Red.paint("[Error]"), e);
     exit(1);
 }
 
 fn callback_error<S: Display, F: Fn() -> ()>(e: S, callback: F) -> ! {
     callback();
+    document::Artifacts::cleanup();
     println!("\n{}: {}", Red.paint("[Error]"), e);
     exit(1)
 }
@@ -43,6 +60,28 @@ fn main() {
         // ATM the changelog is just embedded at build time and the printed to the user
         println!("{}", include_str!("../CHANGELOG.md"));
         exit(0)
+    } else if let Some(themes) = matches.subcommand_matches("themes") {
+        if themes.subcommand_matches("list").is_some() {
+            let (builtin, user) = style::Theme::list();
+            println!("Built-in themes:");
+            for name in builtin {
+                println!("    {}", name);
+            }
+
+            match style::Theme::user_dir() {
+                Some(dir) => println!("\nUser themes ({}):", dir.display()),
+                None => println!("\nUser themes:"),
+            };
+            for name in user {
+                println!("    {}", name);
+            }
+            exit(0)
+        } else {
+            callback_error("Missing subcommand for 'themes'", || {
+                let mut out = stdout();
+                cli_app.write_help(&mut out).unwrap();
+            });
+        }
     } else {
         // if input and output don't have default arguments the 'changelog'
         // subcommand would require input and output too
@@ -65,28 +104,45 @@ fn main() {
         None
     };
 
-    let style = if matches.is_present("stylesheet") {
-        let raw_path = matches.value_of("stylesheet").unwrap();
-        let path = Path::new(raw_path);
+    // custom stylesheets are stacked in the given order on top of the theme
+    // remote resources are fetched according to --remote
+    let remote = remote::Remote::parse(&matches);
 
-        if !path.exists() {
-            warning("Selected stylesheet wasn't found. Falling back to default");
-            style::Stylesheet::default()
-        } else if !path.is_file() {
-            warning("Selected stylesheet isn't a file. Falling back to default");
-            style::Stylesheet::default()
-        } else {
-            match style::Stylesheet::load(&path) {
-                Ok(stylesheet) => stylesheet,
-                Err(e) => error(format!(
-                    "Failed to embedded stylesheet. Possible corrupted binary: {}",
-                    e
-                )),
+    let mut style = style::Stylesheet::default();
+    if let Some(raw_paths) = matches.values_of("stylesheet") {
+        for raw_path in raw_paths {
+            let path = Path::new(raw_path);
+
+            if remote::is_remote(raw_path) {
+                match remote.fetch(raw_path) {
+                    Ok((_, css)) => style
+                        .custom
+                        .push(String::from_utf8_lossy(&css).into_owned()),
+                    Err(e) => error(format!("Failed to load stylesheet {}: {}", raw_path, e)),
+                }
+            } else if !path.exists() {
+                warning(format!("Stylesheet {} wasn't found. Skipping it", raw_path));
+            } else if !path.is_file() {
+                warning(format!("Stylesheet {} isn't a file. Skipping it", raw_path));
+            } else {
+                match style.load(path) {
+                    Ok(_) => (),
+                    Err(e) => error(format!("Failed to load stylesheet {}: {}", raw_path, e)),
+                }
             }
         }
-    } else {
-        style::Stylesheet::default()
-    };
+    }
+
+    style.fonts = fonts::Fonts::parse(&matches).css;
+
+    if let Some(definitions) = matches.values_of("var") {
+        for definition in definitions {
+            match style.set_variable(definition) {
+                Ok(_) => (),
+                Err(e) => error(format!("Invalid theme variable: {}", e)),
+            }
+        }
+    }
 
     // check and evaluate input file
     let input_value = matches.value_of("INPUT").unwrap();
@@ -111,12 +167,58 @@ fn main() {
         Err(e) => error(format!("Couldn't load source file: {}", e)),
     };
 
+    // split front matter (e.g. 'columns: 2') from the document
+    let (front_matter, raw_input) = frontmatter::FrontMatter::split(raw_input);
+
+    // multi-column layout, the cli options take precedence over the front matter
+    let columns = matches
+        .value_of("columns")
+        .or_else(|| front_matter.get("columns"))
+        .unwrap_or("1");
+    let span = matches
+        .value_of("column-span")
+        .or_else(|| front_matter.get("column-span"))
+        .unwrap_or("1");
+    if let Err(e) = style.set_columns(columns, span) {
+        error(format!("Invalid column layout: {}", e));
+    }
+
+    // load translations for static texts and the theme
+    let catalog = locale::Catalog::parse(&matches);
+    let theme = style::Theme::parse(&matches);
+
+    // local files are only loaded from the directory of the input file and --allow-path
+    let sandbox = sandbox::Sandbox::parse(&matches, input_path);
+
     // create html
     let options = convert::build_options(&matches);
-    let output = highlight::parse_html(raw_input, options, matches.is_present("safe"));
-    let rendered = document::Document::build(style, output, &matches);
+    let render = highlight::RenderOptions {
+        policy: sanitize::Policy::parse(&matches),
+        code_theme: &theme.code,
+        tables: highlight::TableOptions::parse(&matches),
+        images: images::ImageOptions::parse(&matches, &sandbox, &remote),
+        footnotes: footnotes::Placement::parse(&matches),
+        bibliography: citations::Bibliography::parse(&matches, &front_matter, &sandbox),
+        numbering: matches.is_present("number-sections")
+            || front_matter.get("number-sections") == Some("true"),
+        links: links::Links::parse(&matches, &sandbox, input_path),
+        extensions: extensions::Extensions::parse(&matches),
+    };
+    let output = highlight::parse_html(raw_input, options, &render, &catalog);
+    // the emoji font is only embedded if the document contains emoji
+    if output.html.contains("<span class=\"emoji\">") {
+        style.fonts.push_str(&fonts::emoji());
+    }
+    let depth = output.max_level();
+    let lists = lists::Lists::parse(&matches, output.figures, output.tables);
+    let content = format!("{}{}", lists.to_html(None), output.html);
+    let rendered = document::Rendered {
+        html: document::Document::build(style, &theme, content, &catalog),
+        depth,
+        lists,
+    };
 
     // convert html
     // this handles all errors with ! and doesn't return a result
-    convert::convert(rendered, name, &matches)
+    convert::convert(rendered, name, &catalog, &theme, &sandbox, &matches)
 }
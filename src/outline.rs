@@ -0,0 +1,72 @@
+use lopdf::{Document, Error as PDFError, Object, ObjectId};
+
+/* PDF outline (bookmarks). wkhtmltopdf builds it from the h1-h6 elements of the document
+but its depth isn't configurable (it's always 4), so --outline-depth prunes the outline of
+the generated PDF instead */
+
+// deepest level of the outline generated by wkhtmltopdf
+pub const GENERATED_DEPTH: u32 = 4;
+
+// first child of an outline item or of the outline root
+fn first(document: &Document, id: ObjectId) -> Option<ObjectId> {
+    document
+        .get_dictionary(id)
+        .and_then(|item| item.get(b"First"))
+        .and_then(Object::as_reference)
+        .ok()
+}
+
+// removes the items below the given level and returns the number of visible items
+// left under the item (the value of /Count for open items)
+fn prune(document: &mut Document, id: ObjectId, level: u32, depth: u32) -> Result<i64, PDFError> {
+    let mut visible = 0;
+
+    if level >= depth {
+        let item = document.get_object_mut(id)?.as_dict_mut()?;
+        item.remove(b"First");
+        item.remove(b"Last");
+        item.remove(b"Count");
+        return Ok(visible);
+    }
+
+    let mut child = first(document, id);
+    while let Some(current) = child {
+        visible += 1 + prune(document, current, level + 1, depth)?;
+        child = document
+            .get_dictionary(current)?
+            .get(b"Next")
+            .and_then(Object::as_reference)
+            .ok();
+    }
+
+    // closed items keep a negative count, their children aren't visible
+    let item = document.get_object_mut(id)?.as_dict_mut()?;
+    let closed = matches!(item.get(b"Count"), Ok(Object::Integer(count)) if *count < 0);
+    if visible == 0 {
+        item.remove(b"Count");
+    } else if closed {
+        item.set("Count", -visible);
+        return Ok(0);
+    } else {
+        item.set("Count", visible);
+    }
+
+    Ok(visible)
+}
+
+// limits the outline of a PDF to the headings up to the given level
+pub fn limit(pdf: &[u8], depth: u32) -> Result<Vec<u8>, PDFError> {
+    let mut document = Document::load_mem(pdf)?;
+
+    let outlines = document
+        .catalog()?
+        .get(b"Outlines")
+        .and_then(Object::as_reference);
+    if let Ok(outlines) = outlines {
+        prune(&mut document, outlines, 0, depth)?;
+    }
+
+    let mut limited = Vec::new();
+    document.save_to(&mut limited)?;
+    Ok(limited)
+}
@@ -0,0 +1,259 @@
+use lopdf::{dictionary, Dictionary, Document, Error as PDFError, Object, ObjectId, Stream};
+use std::collections::HashSet;
+
+/* Print mode: imposes the PDF rendered by wkhtmltopdf for duplex printing and binding */
+
+// options for print mode, lengths are in PDF points
+pub struct PrintOptions {
+    pub gutter: f64,
+    pub crop_marks: bool,
+}
+
+// distance of crop marks from the trimmed page and their length
+static CROP_OFFSET: f64 = 8.5; // 3mm
+static CROP_LENGTH: f64 = 14.2; // 5mm
+
+// numbers in PDF arrays may be integers or reals
+fn number(object: &Object) -> f64 {
+    match *object {
+        Object::Integer(i) => i as f64,
+        Object::Real(r) => r,
+        _ => 0.0,
+    }
+}
+
+// resolves an attribute which may be inherited from the page tree
+fn inherited(document: &Document, page: ObjectId, key: &[u8]) -> Option<Object> {
+    let mut current = document.get_dictionary(page).ok()?;
+
+    loop {
+        if let Ok(value) = current.get(key) {
+            return document
+                .dereference(value)
+                .ok()
+                .map(|(_, object)| object.clone());
+        }
+
+        let parent = current.get(b"Parent").and_then(Object::as_reference).ok()?;
+        current = document.get_dictionary(parent).ok()?;
+    }
+}
+
+fn media_box(document: &Document, page: ObjectId) -> [f64; 4] {
+    match inherited(document, page, b"MediaBox") {
+        Some(Object::Array(values)) if values.len() == 4 => [
+            number(&values[0]),
+            number(&values[1]),
+            number(&values[2]),
+            number(&values[3]),
+        ],
+        // fall back to A4 if the page has no valid media box
+        _ => [0.0, 0.0, 595.0, 842.0],
+    }
+}
+
+fn rectangle(values: [f64; 4]) -> Object {
+    Object::Array(values.iter().map(|value| Object::Real(*value)).collect())
+}
+
+// page an outline item or link annotation points to
+pub fn destination(document: &Document, entry: &Dictionary) -> Option<ObjectId> {
+    // destination is either set directly or as GoTo action
+    let destination = entry.get(b"Dest").ok().or_else(|| {
+        entry
+            .get(b"A")
+            .ok()
+            .and_then(|action| document.dereference(action).ok())
+            .and_then(|(_, action)| action.as_dict().ok())
+            .and_then(|action| action.get(b"D").ok())
+    });
+
+    match destination {
+        Some(Object::Array(destination)) => match destination.first() {
+            Some(Object::Reference(page)) => Some(*page),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// pages the top level outline items (chapters) point to
+fn chapter_pages(document: &Document) -> HashSet<ObjectId> {
+    let mut pages = HashSet::new();
+
+    let outlines = document
+        .catalog()
+        .and_then(|catalog| catalog.get(b"Outlines"))
+        .and_then(Object::as_reference)
+        .and_then(|id| document.get_dictionary(id));
+
+    let mut item = match outlines.and_then(|outlines| outlines.get(b"First")) {
+        Ok(Object::Reference(id)) => Some(*id),
+        _ => None,
+    };
+
+    while let Some(id) = item {
+        let entry = match document.get_dictionary(id) {
+            Ok(entry) => entry,
+            Err(_) => break,
+        };
+
+        if let Some(page) = destination(document, entry) {
+            pages.insert(page);
+        }
+
+        item = entry.get(b"Next").and_then(Object::as_reference).ok();
+    }
+
+    pages
+}
+
+// blank page with the same size as the given one
+fn blank_page(document: &mut Document, parent: ObjectId, size: [f64; 4]) -> ObjectId {
+    let contents = document.add_object(Stream::new(dictionary! {}, Vec::new()));
+    document.add_object(dictionary! {
+        "Type" => "Page",
+        "Parent" => parent,
+        "MediaBox" => rectangle(size),
+        "Resources" => dictionary! {},
+        "Contents" => contents,
+    })
+}
+
+// draws crop marks around the trim box into the bleed area
+fn crop_marks(trim: [f64; 4]) -> Vec<u8> {
+    let [left, bottom, right, top] = trim;
+    let mut operations = String::from("q 0.3 w 0 G\n");
+
+    // corners with the direction pointing away from the page
+    for (x, y, dx, dy) in [
+        (left, bottom, -1.0, -1.0),
+        (right, bottom, 1.0, -1.0),
+        (left, top, -1.0, 1.0),
+        (right, top, 1.0, 1.0),
+    ]
+    .iter()
+    {
+        // horizontal mark
+        operations.push_str(&format!(
+            "{} {} m {} {} l S\n",
+            x + dx * CROP_OFFSET,
+            y,
+            x + dx * (CROP_OFFSET + CROP_LENGTH),
+            y
+        ));
+        // vertical mark
+        operations.push_str(&format!(
+            "{} {} m {} {} l S\n",
+            x,
+            y + dy * CROP_OFFSET,
+            x,
+            y + dy * (CROP_OFFSET + CROP_LENGTH)
+        ));
+    }
+
+    operations.push_str("Q\n");
+    operations.into_bytes()
+}
+
+// wraps the existing page content in q/Q and appends additional content
+fn append_content(
+    document: &mut Document,
+    page: ObjectId,
+    content: Vec<u8>,
+) -> Result<(), PDFError> {
+    let save = document.add_object(Stream::new(dictionary! {}, b"q\n".to_vec()));
+    let restore = document.add_object(Stream::new(dictionary! {}, b"Q\n".to_vec()));
+    let addition = document.add_object(Stream::new(dictionary! {}, content));
+
+    let page = document.get_object_mut(page)?.as_dict_mut()?;
+    let mut contents = vec![Object::Reference(save)];
+    match page.get(b"Contents") {
+        Ok(Object::Reference(id)) => contents.push(Object::Reference(*id)),
+        Ok(Object::Array(existing)) => contents.extend(existing.iter().cloned()),
+        _ => (),
+    };
+    contents.push(Object::Reference(restore));
+    contents.push(Object::Reference(addition));
+
+    page.set("Contents", contents);
+    Ok(())
+}
+
+// applies print mode to the PDF:
+// - chapters (top level outline items) are moved to odd pages by inserting blank pages
+// - the gutter is mirrored on even pages by shifting their visible area
+// - crop marks are drawn into an additional bleed area
+pub fn impose(pdf: &[u8], options: &PrintOptions) -> Result<Vec<u8>, PDFError> {
+    let mut document = Document::load_mem(pdf)?;
+
+    let root = document
+        .catalog()?
+        .get(b"Pages")
+        .and_then(Object::as_reference)?;
+    let chapters = chapter_pages(&document);
+    let original: Vec<ObjectId> = document.get_pages().values().cloned().collect();
+
+    // flatten the page tree while inserting blank pages in front of chapters on even pages
+    let mut pages = Vec::new();
+    for page in original {
+        let size = media_box(&document, page);
+        let resources = inherited(&document, page, b"Resources");
+
+        if pages.len() % 2 == 1 && chapters.contains(&page) {
+            pages.push(blank_page(&mut document, root, size));
+        }
+
+        let dictionary = document.get_object_mut(page)?.as_dict_mut()?;
+        dictionary.set("Parent", root);
+        dictionary.set("MediaBox", rectangle(size));
+        if let Some(resources) = resources {
+            dictionary.set("Resources", resources);
+        }
+        pages.push(page);
+    }
+
+    let tree = document.get_object_mut(root)?.as_dict_mut()?;
+    tree.set(
+        "Kids",
+        pages
+            .iter()
+            .map(|page| Object::Reference(*page))
+            .collect::<Vec<Object>>(),
+    );
+    tree.set("Count", pages.len() as i64);
+
+    for (i, page) in pages.iter().enumerate() {
+        let [left, bottom, right, top] = media_box(&document, *page);
+
+        // the gutter is rendered on the left, even pages have the binding on the right
+        let shift = if (i + 1) % 2 == 0 {
+            options.gutter
+        } else {
+            0.0
+        };
+        let trim = [left + shift, bottom, right + shift, top];
+
+        let media = if options.crop_marks {
+            let bleed = CROP_OFFSET + CROP_LENGTH;
+            append_content(&mut document, *page, crop_marks(trim))?;
+            [
+                trim[0] - bleed,
+                trim[1] - bleed,
+                trim[2] + bleed,
+                trim[3] + bleed,
+            ]
+        } else {
+            trim
+        };
+
+        let dictionary = document.get_object_mut(*page)?.as_dict_mut()?;
+        dictionary.set("MediaBox", rectangle(media));
+        dictionary.set("TrimBox", rectangle(trim));
+        dictionary.remove(b"CropBox");
+    }
+
+    let mut buffer = Vec::new();
+    document.save_to(&mut buffer)?;
+    Ok(buffer)
+}
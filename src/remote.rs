@@ -0,0 +1,256 @@
+use crate::error;
+use clap::ArgMatches;
+use sha2::{Digest, Sha256};
+use std::env::var;
+use std::fs::{create_dir_all, read, read_to_string, write};
+use std::io::Read;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/* Remote resources (--remote): denied, fetched on each run or fetched once into a
+content-addressed cache. Fetched resources are embedded, so wkhtmltopdf doesn't load them */
+#[derive(Clone, Copy, PartialEq)]
+pub enum Mode {
+    Deny,
+    Allow,
+    Cache,
+}
+
+#[derive(Clone)]
+pub struct Remote {
+    pub mode: Mode,
+    cache: Option<PathBuf>,
+}
+
+// upper limit for the size of a single remote resource
+static MAX_SIZE: u64 = 50 * 1024 * 1024;
+static TIMEOUT: Duration = Duration::from_secs(30);
+
+pub fn is_remote(url: &str) -> bool {
+    let lowercase = url.trim_start().to_lowercase();
+    lowercase.starts_with("http://") || lowercase.starts_with("https://")
+}
+
+fn sha256(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+impl Remote {
+    pub fn parse(matches: &ArgMatches) -> Remote {
+        let mode = match matches.value_of("remote").unwrap_or("allow") {
+            "deny" => Mode::Deny,
+            "cache" => Mode::Cache,
+            _ => Mode::Allow,
+        };
+
+        let cache = match matches.value_of("remote-cache") {
+            Some(dir) => Some(PathBuf::from(dir)),
+            None => Self::cache_dir(),
+        };
+        if mode == Mode::Cache && cache.is_none() {
+            error("No cache directory for --remote=cache available, please set one with --remote-cache");
+        }
+
+        Remote::new(mode, cache)
+    }
+
+    pub fn new(mode: Mode, cache: Option<PathBuf>) -> Remote {
+        Remote { mode, cache }
+    }
+
+    // default cache directory (e.g. ~/.cache/mdpdf/remote)
+    pub fn cache_dir() -> Option<PathBuf> {
+        let cache = match var("XDG_CACHE_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match var("HOME").or_else(|_| var("LOCALAPPDATA")) {
+                Ok(home) => PathBuf::from(home).join(".cache"),
+                Err(_) => return None,
+            },
+        };
+
+        Some(cache.join("mdpdf").join("remote"))
+    }
+
+    // fetches a remote resource according to the policy, returns its mime type and content
+    pub fn fetch(&self, url: &str) -> Result<(String, Vec<u8>), String> {
+        match self.mode {
+            Mode::Deny => Err("remote resources are denied (--remote=deny)".to_owned()),
+            Mode::Allow => download(url),
+            Mode::Cache => {
+                if let Some(cached) = self.cached(url) {
+                    return Ok(cached);
+                }
+
+                let (mime, data) = download(url)?;
+                self.store(url, &mime, &data)
+                    .map_err(|e| format!("failed to write to the cache: {}", e))?;
+                Ok((mime, data))
+            }
+        }
+    }
+
+    // the cache has the contents under objects/<sha256 of content> and the url index
+    // under urls/<sha256 of url> with '<sha256 of content> <mime type>'
+    fn cached(&self, url: &str) -> Option<(String, Vec<u8>)> {
+        let cache = self.cache.as_ref()?;
+        let entry = read_to_string(cache.join("urls").join(sha256(url.as_bytes()))).ok()?;
+        let (hash, mime) = entry.trim().split_once(' ')?;
+
+        // corrupted objects are fetched again
+        let data = read(cache.join("objects").join(hash)).ok()?;
+        if sha256(&data) == hash {
+            Some((mime.to_owned(), data))
+        } else {
+            None
+        }
+    }
+
+    fn store(&self, url: &str, mime: &str, data: &[u8]) -> Result<(), std::io::Error> {
+        let cache = match self.cache.as_ref() {
+            Some(cache) => cache,
+            None => return Ok(()),
+        };
+
+        let (objects, urls) = (cache.join("objects"), cache.join("urls"));
+        create_dir_all(&objects)?;
+        create_dir_all(&urls)?;
+
+        let hash = sha256(data);
+        write(objects.join(&hash), data)?;
+        write(
+            urls.join(sha256(url.as_bytes())),
+            format!("{} {}\n", hash, mime),
+        )
+    }
+}
+
+fn download(url: &str) -> Result<(String, Vec<u8>), String> {
+    if !is_remote(url) {
+        return Err("only http and https are supported".to_owned());
+    }
+
+    let response = ureq::get(url)
+        .timeout(TIMEOUT)
+        .call()
+        .map_err(|e| e.to_string())?;
+    let mime = response.content_type().to_owned();
+
+    let mut data = Vec::new();
+    response
+        .into_reader()
+        .take(MAX_SIZE + 1)
+        .read_to_end(&mut data)
+        .map_err(|e| e.to_string())?;
+    if data.len() as u64 > MAX_SIZE {
+        return Err(format!("larger than {} MiB", MAX_SIZE / 1024 / 1024));
+    }
+
+    Ok((mime, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{sha256, Mode, Remote, MAX_SIZE};
+    use std::fs::{remove_file, write};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread::{spawn, JoinHandle};
+    use tempfile::tempdir;
+
+    // local http stand-in answering the given number of requests with the body,
+    // the handle returns the number of requests it served
+    fn serve(body: Vec<u8>, requests: usize) -> (String, JoinHandle<usize>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/image.png", listener.local_addr().unwrap());
+
+        let handle = spawn(move || {
+            let mut served = 0;
+            for stream in listener.incoming().take(requests) {
+                let mut stream = stream.unwrap();
+                let mut request = Vec::new();
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer) {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+
+                let header = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: image/png\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    body.len()
+                );
+                // the client stops reading bodies that are too large
+                let _ = stream.write_all(header.as_bytes());
+                let _ = stream.write_all(&body);
+                served += 1;
+            }
+            served
+        });
+
+        (url, handle)
+    }
+
+    #[test]
+    fn deny_doesnt_fetch() {
+        let remote = Remote::new(Mode::Deny, None);
+        assert!(remote.fetch("http://127.0.0.1:9/image.png").is_err());
+    }
+
+    #[test]
+    fn allow_fetches_the_resource() {
+        let (url, server) = serve(b"image".to_vec(), 1);
+        let remote = Remote::new(Mode::Allow, None);
+
+        let (mime, data) = remote.fetch(&url).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(data, b"image");
+        assert_eq!(server.join().unwrap(), 1);
+    }
+
+    #[test]
+    fn cache_works_offline() {
+        let cache = tempdir().unwrap();
+        let (url, server) = serve(b"image".to_vec(), 1);
+        let remote = Remote::new(Mode::Cache, Some(cache.path().to_path_buf()));
+
+        assert_eq!(remote.fetch(&url).unwrap().1, b"image");
+        // the server is gone after one request, so this is served from the cache
+        assert_eq!(server.join().unwrap(), 1);
+        let (mime, data) = remote.fetch(&url).unwrap();
+        assert_eq!(mime, "image/png");
+        assert_eq!(data, b"image");
+        assert!(cache
+            .path()
+            .join("objects")
+            .join(sha256(b"image"))
+            .is_file());
+    }
+
+    #[test]
+    fn cache_fetches_corrupted_and_missing_objects_again() {
+        let cache = tempdir().unwrap();
+        let (url, server) = serve(b"image".to_vec(), 3);
+        let remote = Remote::new(Mode::Cache, Some(cache.path().to_path_buf()));
+        let object = cache.path().join("objects").join(sha256(b"image"));
+
+        remote.fetch(&url).unwrap();
+        write(&object, b"corrupted").unwrap();
+        assert_eq!(remote.fetch(&url).unwrap().1, b"image");
+        remove_file(&object).unwrap();
+        assert_eq!(remote.fetch(&url).unwrap().1, b"image");
+
+        assert_eq!(server.join().unwrap(), 3);
+        assert!(object.is_file());
+    }
+
+    #[test]
+    fn resources_are_limited_in_size() {
+        let (url, server) = serve(vec![0; MAX_SIZE as usize + 1], 1);
+        let remote = Remote::new(Mode::Allow, None);
+
+        let result = remote.fetch(&url);
+        assert_eq!(result.unwrap_err(), "larger than 50 MiB");
+        server.join().unwrap();
+    }
+}
@@ -0,0 +1,125 @@
+use crate::error;
+use clap::ArgMatches;
+use std::path::{Path, PathBuf};
+
+/* Sandbox for rendering: local files are only embedded from the directory tree of the
+input file and paths allowed with --allow-path, JavaScript is only enabled with --allow-js */
+#[derive(Clone)]
+pub struct Sandbox {
+    pub roots: Vec<PathBuf>,
+    pub javascript: bool,
+    pub network: bool,
+}
+
+impl Sandbox {
+    pub fn parse(matches: &ArgMatches, input: &Path) -> Sandbox {
+        let absolute = input.canonicalize().unwrap_or_else(|_| input.to_path_buf());
+        let mut roots = vec![match absolute.parent() {
+            Some(parent) => parent.to_path_buf(),
+            None => PathBuf::from("."),
+        }];
+
+        if let Some(paths) = matches.values_of("allow-path") {
+            for raw_path in paths {
+                match Path::new(raw_path).canonicalize() {
+                    Ok(path) => roots.push(path),
+                    Err(e) => error(format!("Invalid path {} for --allow-path: {}", raw_path, e)),
+                }
+            }
+        }
+
+        Sandbox {
+            roots,
+            javascript: matches.is_present("allow-js"),
+            network: matches.value_of("remote") != Some("deny"),
+        }
+    }
+
+    // directory of the input file, relative paths in the document are resolved against it
+    pub fn base(&self) -> &Path {
+        &self.roots[0]
+    }
+
+    // checks if a local file is inside of one of the allowed directory trees
+    // symlinks and '..' are resolved before, so they can't be used to escape
+    pub fn allows(&self, path: &Path) -> bool {
+        match path.canonicalize() {
+            Ok(path) => self.roots.iter().any(|root| path.starts_with(root)),
+            Err(_) => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Sandbox;
+    use std::fs::{create_dir, write};
+    use std::path::{Path, PathBuf};
+    use tempfile::{tempdir, TempDir};
+
+    // directory with an input directory (the root) and a file next to it
+    fn setup() -> (TempDir, PathBuf, PathBuf) {
+        let dir = tempdir().unwrap();
+        let root = dir.path().canonicalize().unwrap().join("input");
+        create_dir(&root).unwrap();
+        write(root.join("inside.png"), b"inside").unwrap();
+        let outside = dir.path().canonicalize().unwrap().join("outside.png");
+        write(&outside, b"outside").unwrap();
+        (dir, root, outside)
+    }
+
+    fn sandbox(roots: &[&Path]) -> Sandbox {
+        Sandbox {
+            roots: roots.iter().map(|root| root.to_path_buf()).collect(),
+            javascript: false,
+            network: false,
+        }
+    }
+
+    #[test]
+    fn allows_files_inside_the_root() {
+        let (_dir, root, _) = setup();
+        assert!(sandbox(&[&root]).allows(&root.join("inside.png")));
+    }
+
+    #[test]
+    fn denies_files_outside_the_root() {
+        let (_dir, root, outside) = setup();
+        assert!(!sandbox(&[&root]).allows(&outside));
+    }
+
+    #[test]
+    fn denies_parent_directory_escapes() {
+        let (_dir, root, _) = setup();
+        assert!(!sandbox(&[&root]).allows(&root.join("../outside.png")));
+        assert!(!sandbox(&[&root]).allows(&root.join("sub/../../outside.png")));
+    }
+
+    #[test]
+    fn denies_absolute_paths_outside_the_root() {
+        let (_dir, root, outside) = setup();
+        assert!(outside.is_absolute());
+        assert!(!sandbox(&[&root]).allows(&root.join(&outside)));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn denies_symlinks_out_of_the_root() {
+        let (_dir, root, outside) = setup();
+        std::os::unix::fs::symlink(&outside, root.join("link.png")).unwrap();
+        assert!(!sandbox(&[&root]).allows(&root.join("link.png")));
+    }
+
+    #[test]
+    fn allows_files_of_allowed_paths() {
+        let (dir, root, outside) = setup();
+        let allowed = dir.path().canonicalize().unwrap();
+        assert!(sandbox(&[&root, &allowed]).allows(&outside));
+    }
+
+    #[test]
+    fn denies_missing_files() {
+        let (_dir, root, _) = setup();
+        assert!(!sandbox(&[&root]).allows(&root.join("missing.png")));
+    }
+}
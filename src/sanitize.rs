@@ -0,0 +1,400 @@
+use crate::locale::parse_entries;
+use crate::{error, info, warning};
+use ammonia::{Builder, UrlRelative};
+use clap::ArgMatches;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::read_to_string;
+use std::iter::once;
+use std::path::Path;
+
+/* Sanitization policies for the generated html (--sanitize, --sanitize-policy) */
+#[derive(PartialEq)]
+enum Level {
+    Strict,
+    Default,
+    Permissive,
+    None,
+}
+
+pub struct Policy {
+    level: Level,
+    tags: Vec<String>,
+    attributes: Vec<String>,
+    tag_attributes: Vec<(String, String)>,
+    classes: Vec<(String, String)>,
+    schemes: Vec<String>,
+    verbose: bool,
+}
+
+// classes of the markup generated by mdpdf itself, allowed by every policy
+static MDPDF_CLASSES: &[(&str, &[&str])] = &[
+    ("input", &["form-check-input"]),
+    (
+        "div",
+        &[
+            "form-check",
+            "break",
+            "footnotes",
+            "footnotes-block",
+            "footnote",
+            "landscape",
+            "references",
+            "reference",
+        ],
+    ),
+    (
+        "span",
+        &[
+            "break",
+            "footnote-label",
+            "figure",
+            "figcaption",
+            "citation",
+            "reference-label",
+            "section-number",
+            "emoji",
+        ],
+    ),
+    ("sup", &["footnote-reference"]),
+    ("a", &["footnote-backref", "cross-reference"]),
+    ("table", &["zebra"]),
+    ("th", &["align-left", "align-center", "align-right"]),
+    ("td", &["align-left", "align-center", "align-right"]),
+];
+
+// tags generated from markdown, the only ones allowed by the strict policy
+static STRICT_TAGS: &[&str] = &[
+    "a",
+    "abbr",
+    "blockquote",
+    "br",
+    "caption",
+    "code",
+    "dd",
+    "del",
+    "div",
+    "dl",
+    "dt",
+    "em",
+    "figcaption",
+    "figure",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "hr",
+    "img",
+    "input",
+    "kbd",
+    "li",
+    "mark",
+    "ol",
+    "p",
+    "pre",
+    "span",
+    "strong",
+    "sub",
+    "sup",
+    "table",
+    "tbody",
+    "td",
+    "th",
+    "thead",
+    "tr",
+    "ul",
+];
+
+// attributes of the generated markup for the strict policy (highlighted code and image sizes use style)
+static STRICT_ATTRIBUTES: &[(&str, &[&str])] = &[
+    ("a", &["href", "title"]),
+    ("abbr", &["title"]),
+    ("img", &["src", "alt", "title", "width", "height", "style"]),
+    ("input", &["type", "checked", "disabled"]),
+    ("ol", &["start"]),
+    ("pre", &["style"]),
+    ("span", &["style"]),
+];
+
+// additional tags and attributes for the permissive policy
+static PERMISSIVE_TAGS: &[&str] = &[
+    "section", "main", "address", "font", "label", "meter", "progress", "svg", "path", "g",
+    "circle", "rect", "line", "polyline", "polygon", "text",
+];
+static PERMISSIVE_ATTRIBUTES: &[&str] = &[
+    "style",
+    "type",
+    "checked",
+    "id",
+    "class",
+    "align",
+    "width",
+    "height",
+    "dir",
+    "color",
+    "value",
+    "max",
+    "min",
+    "viewbox",
+    "d",
+    "fill",
+    "stroke",
+    "stroke-width",
+    "x",
+    "y",
+    "cx",
+    "cy",
+    "r",
+    "x1",
+    "y1",
+    "x2",
+    "y2",
+    "points",
+    "xmlns",
+];
+
+// embedded images are data uris
+static URL_SCHEMES: &[&str] = &["data"];
+
+impl Policy {
+    pub fn parse(matches: &ArgMatches) -> Policy {
+        let level = if matches.is_present("unsafe") {
+            Level::None
+        } else {
+            match matches.value_of("sanitize").unwrap_or("default") {
+                "strict" => Level::Strict,
+                "permissive" => Level::Permissive,
+                "none" => Level::None,
+                _ => Level::Default,
+            }
+        };
+
+        let mut policy = Policy {
+            level,
+            tags: Vec::new(),
+            attributes: Vec::new(),
+            tag_attributes: Vec::new(),
+            classes: Vec::new(),
+            schemes: Vec::new(),
+            verbose: matches.is_present("verbose"),
+        };
+
+        if let Some(path) = matches.value_of("sanitize-policy") {
+            if let Err(e) = policy.extend(Path::new(path)) {
+                error(format!(
+                    "Failed to load sanitization policy {}: {}",
+                    path, e
+                ));
+            }
+        }
+
+        if policy.level == Level::Permissive && !policy.classes.is_empty() {
+            warning("The classes of the sanitization policy are ignored, --sanitize permissive allows all classes");
+        }
+
+        policy
+    }
+
+    // extends the policy with a user policy file. Lines are 'key = comma separated values':
+    // tags, attributes ('id' for all tags or 'img.loading'), classes ('div.note') and schemes
+    pub fn extend(&mut self, path: &Path) -> Result<(), String> {
+        let source = read_to_string(path).map_err(|e| e.to_string())?;
+        let entries = parse_entries(&source)?;
+
+        for (key, value) in entries.iter() {
+            let values = value
+                .split(',')
+                .map(|value| value.trim().to_lowercase())
+                .filter(|value| !value.is_empty());
+
+            match key.as_str() {
+                "tags" => self.tags.extend(values),
+                "schemes" => self.schemes.extend(values),
+                "attributes" => {
+                    for value in values {
+                        // ammonia only allows classes one by one
+                        if value == "class" || value.ends_with(".class") {
+                            return Err(format!(
+                                "attribute '{}' isn't supported, allow classes with 'classes = tag.class' (e.g. div.note)",
+                                value
+                            ));
+                        }
+
+                        match value.split_once('.') {
+                            Some((tag, attribute)) => self
+                                .tag_attributes
+                                .push((tag.to_owned(), attribute.to_owned())),
+                            None => self.attributes.push(value),
+                        }
+                    }
+                }
+                "classes" => {
+                    for value in values {
+                        match value.split_once('.') {
+                            Some((tag, class)) => {
+                                self.classes.push((tag.to_owned(), class.to_owned()))
+                            }
+                            None => {
+                                return Err(format!(
+                                    "class '{}' must be given as tag.class (e.g. div.note)",
+                                    value
+                                ))
+                            }
+                        }
+                    }
+                }
+                key => {
+                    return Err(format!(
+                        "unknown key '{}'. Possible keys: tags, attributes, classes, schemes",
+                        key
+                    ))
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // cleans the html according to the policy
+    pub fn clean(&self, html: String) -> String {
+        if self.level == Level::None {
+            return html;
+        }
+
+        let mut builder = Builder::default();
+        builder
+            .link_rel(None)
+            .url_relative(UrlRelative::PassThrough)
+            .add_url_schemes(URL_SCHEMES)
+            .add_url_schemes(&self.schemes);
+
+        match self.level {
+            Level::Strict => {
+                builder
+                    .tags(STRICT_TAGS.iter().copied().collect())
+                    .generic_attributes(once("id").collect())
+                    .tag_attributes(
+                        STRICT_ATTRIBUTES
+                            .iter()
+                            .map(|(tag, attributes)| (*tag, attributes.iter().copied().collect()))
+                            .collect(),
+                    );
+            }
+            Level::Default => {
+                builder
+                    .add_tags(&["input"])
+                    .add_generic_attributes(&["style", "type", "checked", "id"]);
+            }
+            _ => {
+                builder
+                    .add_tags(&["input"])
+                    .add_tags(PERMISSIVE_TAGS)
+                    .add_generic_attributes(PERMISSIVE_ATTRIBUTES);
+            }
+        };
+
+        // additions from the user policy file
+        builder
+            .add_tags(&self.tags)
+            .add_generic_attributes(&self.attributes);
+        for (tag, attribute) in self.tag_attributes.iter() {
+            builder.add_tag_attributes(tag, once(attribute));
+        }
+
+        // the permissive policy allows all classes, ammonia doesn't allow to combine both
+        if self.level != Level::Permissive {
+            for (tag, classes) in MDPDF_CLASSES {
+                builder.add_allowed_classes(*tag, *classes);
+            }
+            for (tag, class) in self.classes.iter() {
+                builder.add_allowed_classes(tag, once(class));
+            }
+        }
+
+        let cleaned = builder.clean(&html).to_string();
+        if self.verbose {
+            report(&html, &cleaned);
+        }
+        cleaned
+    }
+}
+
+// counts tags ('img'), attributes ('img[src]') and classes ('div.note') of html
+fn inventory(html: &str) -> HashMap<String, usize> {
+    let mut counts = HashMap::new();
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        rest = &rest[start + 1..];
+        let name_end = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+            .unwrap_or(rest.len());
+        if name_end == 0 {
+            // closing tags, comments and doctype
+            continue;
+        }
+
+        let tag = rest[..name_end].to_lowercase();
+        *counts.entry(tag.clone()).or_insert(0) += 1;
+        rest = &rest[name_end..];
+
+        // attributes until the end of the tag
+        loop {
+            rest = rest.trim_start_matches(|c: char| c.is_whitespace() || c == '/');
+            if rest.is_empty() || rest.starts_with('>') {
+                break;
+            }
+
+            let attribute_end = rest
+                .find(|c: char| c.is_whitespace() || c == '=' || c == '>' || c == '/')
+                .unwrap_or(rest.len())
+                .max(1);
+            let attribute = rest[..attribute_end].to_lowercase();
+            rest = rest[attribute_end..].trim_start();
+
+            let mut value = "";
+            if let Some(after) = rest.strip_prefix('=') {
+                let after = after.trim_start();
+                let (quoted, end) = match after.chars().next() {
+                    Some(quote) if quote == '"' || quote == '\'' => {
+                        (1, after[1..].find(quote).map(|i| i + 1))
+                    }
+                    _ => (0, after.find(|c: char| c.is_whitespace() || c == '>')),
+                };
+                let end = end.unwrap_or(after.len());
+                value = &after[quoted..end];
+                rest = &after[(end + quoted).min(after.len())..];
+            }
+
+            *counts.entry(format!("{}[{}]", tag, attribute)).or_insert(0) += 1;
+            if attribute == "class" {
+                for class in value.split_whitespace() {
+                    *counts.entry(format!("{}.{}", tag, class)).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    counts
+}
+
+// reports tags, attributes and classes removed by the sanitizer (--verbose)
+fn report(original: &str, cleaned: &str) {
+    let after = inventory(cleaned);
+    let stripped: BTreeMap<String, usize> = inventory(original)
+        .into_iter()
+        .filter_map(|(item, count)| {
+            let remaining = after.get(&item).copied().unwrap_or(0);
+            if count > remaining {
+                Some((item, count - remaining))
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    for (item, count) in stripped {
+        info(format!("Sanitizer stripped {} ({}x)", item, count));
+    }
+}
@@ -1,59 +1,432 @@
-use crate::document::Languages;
+use crate::error;
+use crate::fonts::{embed_file, is_font_file};
+use crate::locale::Catalog;
 use clap::ArgMatches;
-use std::fs::File;
+use std::env::var;
+use std::ffi::OsStr;
+use std::fs::{read_dir, read_to_string, File};
 use std::include_str;
-use std::io::{BufReader, Error as IOError, Read};
-use std::path::Path;
+use std::io::{BufReader, Error as IOError, ErrorKind, Read};
+use std::path::{Path, PathBuf};
+use syntect::highlighting::{Theme as CodeTheme, ThemeSet};
 
 /* Stylesheets */
 pub struct Stylesheet {
-    pub en: String,
-    pub de: String,
+    pub custom: Vec<String>,
+    pub variables: String,
+    pub fonts: String,
+    pub columns: String,
     pub main: &'static str,
 }
 
-pub struct Themes;
+/* Themes - either built-in or a theme package directory */
+pub struct Theme {
+    pub css: String,
+    pub header: Option<String>,
+    pub footer: Option<String>,
+    pub code: CodeTheme,
+}
+
+impl Theme {
+    pub fn parse(matches: &ArgMatches) -> Theme {
+        let value = matches.value_of("theme").unwrap();
+
+        // build a theme from user variables on top of the selected built-in one
+        if let Some(raw_path) = matches.value_of("theme-vars") {
+            let variables = match read_to_string(raw_path) {
+                Ok(variables) => variables,
+                Err(e) => error(format!(
+                    "Failed to load theme variables {}: {}",
+                    raw_path, e
+                )),
+            };
+
+            let css = match Self::compile(value, &variables) {
+                Ok(css) => css,
+                Err(e) => error(format!(
+                    "Failed to compile theme variables {}: {}",
+                    raw_path, e
+                )),
+            };
+
+            return Theme {
+                css,
+                header: None,
+                footer: None,
+                code: Self::default_code_theme(),
+            };
+        }
+
+        if let Some(css) = Self::builtin(value) {
+            return Theme {
+                css: css.to_owned(),
+                header: None,
+                footer: None,
+                code: Self::default_code_theme(),
+            };
+        }
+
+        // --theme may either be a path to a theme package or the name of a user theme
+        let path = Path::new(value);
+        let package = if path.is_dir() {
+            path.to_path_buf()
+        } else {
+            match Self::user_dir().map(|dir| dir.join(value)) {
+                Some(dir) if dir.is_dir() => dir,
+                _ => error(format!(
+                    "Theme '{}' wasn't found. Use 'mdpdf themes list' to see all available themes",
+                    value
+                )),
+            }
+        };
+
+        match Self::load(&package) {
+            Ok(theme) => theme,
+            Err(e) => error(format!(
+                "Failed to load theme package {}: {}",
+                package.display(),
+                e
+            )),
+        }
+    }
+
+    fn builtin(name: &str) -> Option<&'static str> {
+        match name {
+            "lime" => Some(include_str!("assets/css/lime.css")),
+            "night" => Some(include_str!("assets/css/night.css")),
+            "light" => Some(include_str!("assets/css/light.css")),
+            _ => None,
+        }
+    }
+
+    // compiles the embedded scss sources of a built-in theme with variables prepended
+    // all theme variables are !default so the user supplied ones take precedence
+    pub fn compile(base: &str, variables: &str) -> Result<String, String> {
+        let defaults = match THEME_VARIABLES.iter().find(|(name, _)| *name == base) {
+            Some((_, defaults)) => defaults,
+            None => {
+                return Err(format!(
+                    "'{}' isn't a built-in theme. Possible base themes: {}",
+                    base,
+                    THEMES.join(", ")
+                ))
+            }
+        };
+
+        let source = format!("{}\n{}\n{}", variables, defaults, COLOR_SCSS);
+        let options = grass::Options::default().style(grass::OutputStyle::Compressed);
+        grass::from_string(source, &options).map_err(|e| e.to_string())
+    }
+
+    fn default_code_theme() -> CodeTheme {
+        ThemeSet::load_defaults().themes["base16-ocean.dark"].clone()
+    }
 
-impl Themes {
-    pub fn parse(matches: &ArgMatches) -> String {
-        match matches.value_of("theme").unwrap() {
-            "lime" => include_str!("assets/css/lime.css"),
-            "night" => include_str!("assets/css/night.css"),
-            _ => include_str!("assets/css/light.css"),
+    // directory for user theme packages (e.g. ~/.config/mdpdf/themes/)
+    pub fn user_dir() -> Option<PathBuf> {
+        let config = match var("XDG_CONFIG_HOME") {
+            Ok(dir) if !dir.is_empty() => PathBuf::from(dir),
+            _ => match var("HOME").or_else(|_| var("APPDATA")) {
+                Ok(home) => PathBuf::from(home).join(".config"),
+                Err(_) => return None,
+            },
+        };
+
+        Some(config.join("mdpdf").join("themes"))
+    }
+
+    // names of all built-in and discovered user themes
+    pub fn list() -> (Vec<&'static str>, Vec<String>) {
+        let mut user = Vec::new();
+
+        if let Some(Ok(entries)) = Self::user_dir().map(read_dir) {
+            for entry in entries.flatten() {
+                if entry.path().is_dir() {
+                    user.push(entry.file_name().to_string_lossy().into_owned());
+                }
+            }
+        }
+        user.sort();
+
+        (THEMES.to_vec(), user)
+    }
+
+    // loads a theme package:
+    // *.css (required, loaded alphabetically), header.html, footer.html,
+    // code.tmTheme (syntax highlighting) and fonts/ (*.ttf, *.otf, *.woff, *.woff2)
+    pub fn load(dir: &Path) -> Result<Theme, IOError> {
+        let mut stylesheets = Vec::new();
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension() == Some(OsStr::new("css")) {
+                stylesheets.push(path);
+            }
+        }
+        stylesheets.sort();
+
+        if stylesheets.is_empty() {
+            return Err(IOError::new(
+                ErrorKind::NotFound,
+                "the theme package contains no stylesheet (*.css)",
+            ));
         }
-        .to_owned()
+
+        let mut css = Self::font_faces(&dir.join("fonts"))?;
+        for path in stylesheets {
+            css.push_str(&read_to_string(path)?);
+        }
+
+        let optional = |name: &str| -> Result<Option<String>, IOError> {
+            let path = dir.join(name);
+            if path.is_file() {
+                Ok(Some(read_to_string(path)?))
+            } else {
+                Ok(None)
+            }
+        };
+
+        let code_path = dir.join("code.tmTheme");
+        let code = if code_path.is_file() {
+            match ThemeSet::get_theme(&code_path) {
+                Ok(theme) => theme,
+                Err(e) => {
+                    return Err(IOError::new(
+                        ErrorKind::InvalidData,
+                        format!("invalid code.tmTheme: {}", e),
+                    ))
+                }
+            }
+        } else {
+            Self::default_code_theme()
+        };
+
+        Ok(Theme {
+            css,
+            header: optional("header.html")?,
+            footer: optional("footer.html")?,
+            code,
+        })
+    }
+
+    // embedded @font-face rules for all fonts of a theme package, the family is the file name
+    fn font_faces(dir: &Path) -> Result<String, IOError> {
+        let mut css = String::new();
+        if !dir.is_dir() {
+            return Ok(css);
+        }
+
+        for entry in read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && is_font_file(&path) {
+                css.push_str(&embed_file(&path)?.1);
+            }
+        }
+
+        Ok(css)
     }
 }
 
 impl Stylesheet {
     pub fn default() -> Self {
         Stylesheet {
-            en: include_str!("assets/css/en.css").to_owned(),
-            de: include_str!("assets/css/de.css").to_owned(),
+            custom: Vec::new(),
+            variables: String::new(),
+            fonts: String::new(),
+            columns: String::new(),
             main: MAIN_STYLESHEET,
         }
     }
 
-    pub fn local(&self, language: &Languages) -> String {
-        match *language {
-            Languages::DE => self.de.clone(),
-            Languages::EN => self.en.clone(),
-        }
+    // localized static texts (e.g. headings) generated from the translation catalog
+    pub fn localized(catalog: &Catalog) -> String {
+        format!(
+            "nav::before{{content:{}}}.footnotes:not(.footnotes-block)::before{{content:{}}}.references::before{{content:{}}}.list-of-figures::before{{content:{}}}.list-of-tables::before{{content:{}}}",
+            css_string(catalog.get("table-of-contents")),
+            css_string(catalog.get("footnotes")),
+            css_string(catalog.get("references")),
+            css_string(catalog.get("list-of-figures")),
+            css_string(catalog.get("list-of-tables"))
+        )
     }
 
-    pub fn load(path: &Path) -> Result<Stylesheet, IOError> {
+    // adds a custom stylesheet on top of the already loaded ones
+    pub fn load(&mut self, path: &Path) -> Result<(), IOError> {
         let file = File::open(path)?;
         let mut reader = BufReader::new(file);
         let mut buffer = String::new();
         // read into buffer
         reader.read_to_string(&mut buffer)?;
 
-        Ok(Stylesheet {
-            main: MAIN_STYLESHEET,
-            en: buffer.clone(),
-            de: buffer,
+        self.custom.push(buffer);
+        Ok(())
+    }
+
+    // multi-column layout for the body, headings up to span level span all columns
+    pub fn set_columns(&mut self, count: &str, span: &str) -> Result<(), String> {
+        let count = match count.trim().parse::<u32>() {
+            Ok(count) if (1..=6).contains(&count) => count,
+            _ => return Err(format!("'{}' columns: expected 1 to 6 columns", count)),
+        };
+        let span = match span.trim().parse::<usize>() {
+            Ok(span) if (1..=6).contains(&span) => span,
+            _ => {
+                return Err(format!(
+                    "'{}' column span: expected a heading level from 1 to 6",
+                    span
+                ))
+            }
+        };
+
+        if count == 1 {
+            self.columns = String::new();
+            return Ok(());
+        }
+
+        let headings: Vec<String> = (1..=span).map(|level| format!("h{}", level)).collect();
+        self.columns = format!(
+            "body{{-webkit-column-count:{0};column-count:{0}}}{1}{{-webkit-column-span:all;column-span:all}}",
+            count,
+            headings.join(",")
+        );
+        Ok(())
+    }
+
+    // sets a theme variable (e.g. primary=#c00) overriding the value of the theme
+    // wkhtmltopdf doesn't support var() so the rules of color.scss using the variable are
+    // compiled with it, the declarations using other variables are left out
+    pub fn set_variable(&mut self, definition: &str) -> Result<(), String> {
+        let (name, value) = match definition.split_once('=') {
+            Some((name, value)) => (name.trim(), value.trim()),
+            None => return Err(format!("'{}' must be written as name=value", definition)),
+        };
+
+        if value.is_empty() || value.contains(|c| ";{}<>\\$".contains(c)) {
+            return Err(format!("'{}' is not a valid value for {}", value, name));
+        }
+
+        let names = variable_names();
+        if !names.contains(&name) {
+            return Err(format!(
+                "Unknown variable '{}'. Possible variables: {}",
+                name,
+                names.join(", ")
+            ));
+        }
+
+        // declarations that don't change with the variable are already part of the theme
+        let constant = compile_color(&names, None)?;
+        let constant = declarations(&constant);
+        let overridden = compile_color(&names, Some((name, value)))?;
+
+        self.variables
+            .push_str(&format!(":root{{--{}:{}}}", name, value));
+        for (selector, declaration) in declarations(&overridden) {
+            if !declaration.contains(UNSET) && !constant.contains(&(selector, declaration)) {
+                self.variables
+                    .push_str(&format!("{}{{{}}}", selector, declaration));
+            }
+        }
+        Ok(())
+    }
+}
+
+// names of the theme variables, all built-in themes define the same ones
+fn variable_names() -> Vec<&'static str> {
+    THEME_VARIABLES[0]
+        .1
+        .lines()
+        .filter_map(|line| line.strip_prefix('$')?.split_once(':'))
+        .map(|(name, _)| name.trim())
+        .collect()
+}
+
+// compiles color.scss with one variable set, all others are unset
+fn compile_color(names: &[&str], variable: Option<(&str, &str)>) -> Result<String, String> {
+    let mut source = String::new();
+    for name in names {
+        let value = match variable {
+            Some((variable, value)) if variable == *name => value,
+            _ => UNSET,
+        };
+        source.push_str(&format!("${}: {};\n", name, value));
+    }
+    source.push_str(COLOR_SCSS);
+
+    let options = grass::Options::default().style(grass::OutputStyle::Compressed);
+    grass::from_string(source, &options).map_err(|e| e.to_string())
+}
+
+// selectors and declarations of compiled css, e.g. ('th', 'color:#c00')
+fn declarations(css: &str) -> Vec<(&str, &str)> {
+    css.split('}')
+        .filter_map(|rule| rule.split_once('{'))
+        .flat_map(|(selector, declarations)| {
+            declarations
+                .split(';')
+                .filter(|declaration| !declaration.is_empty())
+                .map(move |declaration| (selector.trim(), declaration))
         })
+        .collect()
+}
+
+// quotes a text as css string, escaping everything that could end the string or the style tag
+pub fn css_string(text: &str) -> String {
+    let mut quoted = String::from("'");
+    for c in text.chars() {
+        match c {
+            '\'' | '\\' | '<' | '>' | '\n' => quoted.push_str(&format!("\\{:x} ", c as u32)),
+            c => quoted.push(c),
+        }
     }
+    quoted.push('\'');
+    quoted
 }
 
+// built-in themes
+static THEMES: &[&str] = &["light", "lime", "night"];
+
+// scss sources of the built-in themes for compiling themes at runtime
+static THEME_VARIABLES: &[(&str, &str)] = &[
+    (
+        "light",
+        include_str!("assets/scss/themes/light-variables.scss"),
+    ),
+    (
+        "lime",
+        include_str!("assets/scss/themes/lime-variables.scss"),
+    ),
+    (
+        "night",
+        include_str!("assets/scss/themes/night-variables.scss"),
+    ),
+];
+static COLOR_SCSS: &str = include_str!("assets/scss/color.scss");
+
+// value of the variables that aren't set when compiling color.scss for --var
+static UNSET: &str = "mdpdf-unset";
+
 pub static MAIN_STYLESHEET: &'static str = include_str!("assets/css/main.css");
+
+#[cfg(test)]
+mod tests {
+    use super::{variable_names, Stylesheet, UNSET};
+
+    #[test]
+    fn compiles_rules_using_a_variable() {
+        let mut style = Stylesheet::default();
+        style.set_variable("special-text=#c00").unwrap();
+        assert_eq!(
+            style.variables,
+            ":root{--special-text:#c00}th{color:#c00}.form-check-input{background-color:#c00}"
+        );
+        assert!(!style.variables.contains(UNSET));
+    }
+
+    #[test]
+    fn rejects_unknown_variables() {
+        let mut style = Stylesheet::default();
+        assert!(style.set_variable("primary=red;}").is_err());
+        assert!(style.set_variable("unknown=red").is_err());
+        assert!(variable_names().contains(&"font-size"));
+    }
+}